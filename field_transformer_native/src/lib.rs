@@ -0,0 +1,389 @@
+//! The native FieldTransformer ports as a Python extension module.
+//!
+//! Each port is exported under its method's name with its signature, minus
+//! `self`, so the Python class can hand hot methods to Rust without its
+//! callers noticing:
+//!
+//! ```python
+//! import field_transformer_native as native
+//!
+//! class FieldTransformer:
+//!     def to_date_index(self, value):
+//!         return native.to_date_index(value)
+//! ```
+//!
+//! Arguments go through `convert` like records do on the Rust host, so a
+//! `datetime` arrives as its `isoformat()` string, which the `datetime_to_*`
+//! ports accept; the BSON date methods take it the way Python does. Other
+//! methods see the string where Python would see the `datetime`.
+//! Results come back as the Python method returns them, including the
+//! tuple of `extract_ate_id` and the `datetime` of `bson_date_to_datetime`,
+//! and errors as the built-in exception the method would raise.
+//!
+//! Built as an abi3 wheel for CPython 3.7 and later:
+//!
+//! ```sh
+//! maturin build --release
+//! ```
+//!
+//! The tests embed Python instead of being loaded by it, so they run without
+//! the `extension-module` feature:
+//!
+//! ```sh
+//! cargo test --no-default-features
+//! ```
+
+use pyo3::{
+    exceptions::{PyBaseException, PyRuntimeError, PyTypeError, PyValueError},
+    prelude::*,
+    types::{PyDict, PyTuple, PyType},
+    wrap_pyfunction,
+};
+use pyo3_tests::{
+    convert::{from_py, to_py},
+    native, TransformError,
+};
+use serde_json::{Map, Value};
+
+/// Run the port of `method` on `value`.
+fn call(py: Python, method: &str, value: &PyAny, params: Map<String, Value>) -> PyResult<PyObject> {
+    let (port, value) = match bson_datetime(py, method, value)? {
+        Some((port, isoformat)) => (port, Value::String(isoformat)),
+        None => (method, from_py(value).map_err(|err| raise(py, err))?),
+    };
+    let port = native::lookup(port).expect("exported methods have ports");
+    let result = port(&value, &params).map_err(|err| raise(py, err))?;
+    restore(py, method, result)
+}
+
+/// The port and argument for a `datetime`, bare or as `$date`, given to a
+/// method that takes BSON dates. Python branches on the type there, where
+/// the ports only see strings.
+fn bson_datetime(
+    py: Python,
+    method: &str,
+    value: &PyAny,
+) -> PyResult<Option<(&'static str, String)>> {
+    let port = match method {
+        "to_date_index" => "datetime_to_date_index",
+        "bson_date_to_time_index" => "datetime_to_time_index",
+        "bson_date_to_datetime" => "bson_date_to_datetime",
+        _ => return Ok(None),
+    };
+    let date = match value.get_item("$date") {
+        Ok(date) if value.is_instance::<PyDict>()? => date,
+        _ => value,
+    };
+    let datetime = py.import("datetime")?.getattr("datetime")?;
+    if !datetime.downcast::<PyType>()?.is_instance(date)? {
+        return Ok(None);
+    }
+    // What Python's `bson_date_to_datetime` reformats it to.
+    let isoformat = match method {
+        "bson_date_to_datetime" => date.call_method1("strftime", ("%Y-%m-%dT%H:%M:%S.%f",))?,
+        _ => date.call_method0("isoformat")?,
+    };
+    Ok(Some((port, isoformat.extract()?)))
+}
+
+/// The Python value of a result, with the types `convert` flattens put
+/// back.
+fn restore(py: Python, method: &str, result: Value) -> PyResult<PyObject> {
+    match (method, &result) {
+        ("extract_ate_id", Value::Array(items)) => {
+            Ok(PyTuple::new(py, items.iter().map(|item| to_py(py, item))).into())
+        }
+        ("bson_date_to_datetime", Value::String(isoformat)) => Ok(py
+            .import("datetime")?
+            .getattr("datetime")?
+            .call_method1("fromisoformat", (isoformat,))?
+            .into()),
+        _ => Ok(to_py(py, &result)),
+    }
+}
+
+/// The exception the Python method would have raised.
+fn raise(py: Python, err: TransformError) -> PyErr {
+    match err {
+        TransformError::Python {
+            type_name, message, ..
+        } => {
+            let builtin = py
+                .import("builtins")
+                .and_then(|builtins| builtins.getattr(type_name.as_str()))
+                .ok()
+                .and_then(|class| class.downcast::<PyType>().ok())
+                .filter(|class| class.is_subclass::<PyBaseException>().unwrap_or(false));
+            match builtin {
+                // The message is `str()` of the error, the repr of the key.
+                Some(class) if type_name == "KeyError" => {
+                    let key = py
+                        .import("ast")
+                        .and_then(|ast| ast.call_method1("literal_eval", (&message,)))
+                        .map(PyObject::from)
+                        .unwrap_or_else(|_| message.into_py(py));
+                    PyErr::from_type(class, (key,))
+                }
+                Some(class) => PyErr::from_type(class, message),
+                // iso8601's, a ValueError.
+                None if type_name == "ParseError" => PyValueError::new_err(message),
+                None => PyRuntimeError::new_err(format!("{}: {}", type_name, message)),
+            }
+        }
+        TransformError::Conversion(message) => PyTypeError::new_err(message),
+        err => PyRuntimeError::new_err(err.to_string()),
+    }
+}
+
+/// An argument Python only tests for truth.
+struct Truthy(bool);
+
+impl<'source> FromPyObject<'source> for Truthy {
+    fn extract(value: &'source PyAny) -> PyResult<Self> {
+        value.is_true().map(Truthy)
+    }
+}
+
+fn keep_zero_microsecond(keep: Truthy) -> Map<String, Value> {
+    let mut params = Map::new();
+    params.insert("keep_zero_microsecond".into(), Value::Bool(keep.0));
+    params
+}
+
+#[pyfunction(keep_zero_microsecond = "Truthy(true)")]
+#[pyo3(text_signature = "(value, keep_zero_microsecond=True)")]
+fn datetime_to_db_datetime(
+    py: Python,
+    value: &PyAny,
+    keep_zero_microsecond: Truthy,
+) -> PyResult<PyObject> {
+    let params = self::keep_zero_microsecond(keep_zero_microsecond);
+    call(py, "datetime_to_db_datetime", value, params)
+}
+
+#[pyfunction(keep_zero_microsecond = "Truthy(true)")]
+#[pyo3(text_signature = "(value, keep_zero_microsecond=True)")]
+fn convert_bson_rfc3339_datetime(
+    py: Python,
+    value: &PyAny,
+    keep_zero_microsecond: Truthy,
+) -> PyResult<PyObject> {
+    let params = self::keep_zero_microsecond(keep_zero_microsecond);
+    call(py, "convert_bson_rfc3339_datetime", value, params)
+}
+
+/// Methods of one argument, grouped by its name: each group gives the
+/// argument, its text signature and the methods taking it.
+macro_rules! ports {
+    ($($arg:ident $signature:tt: $($method:ident),+;)+) => {
+        $($(
+            #[pyfunction]
+            #[pyo3(text_signature = $signature)]
+            fn $method(py: Python, $arg: &PyAny) -> PyResult<PyObject> {
+                call(py, stringify!($method), $arg, Map::new())
+            }
+        )+)+
+
+        fn add_ports(module: &PyModule) -> PyResult<()> {
+            module.add_function(wrap_pyfunction!(datetime_to_db_datetime, module)?)?;
+            module.add_function(wrap_pyfunction!(convert_bson_rfc3339_datetime, module)?)?;
+            $($(module.add_function(wrap_pyfunction!($method, module)?)?;)+)+
+            Ok(())
+        }
+    };
+}
+
+ports! {
+    value "(value)":
+        datetime_to_date_index,
+        datetime_to_time_index,
+        unix_timestamp_to_db_date_time,
+        unix_timestamp_to_date_index,
+        unix_timestamp_to_time_index,
+        normalize_second_timestamp,
+        normalize_millisecond_timestamp,
+        epoch_to_iso_8601,
+        convert_bson_object_id,
+        convert_bson_long_datetime,
+        to_date_index,
+        bson_date_to_datetime,
+        bson_date_to_time_index,
+        serial_number_to_product_code,
+        serial_number_to_unique_identifier,
+        serial_number_to_owner,
+        crc32_to_app_name,
+        normalize_brand_id,
+        transform_db_name_to_brand,
+        transform_project_name_to_brand,
+        extract_sku;
+    sn_prefix "(sn_prefix)": extract_device_type_from_sn_prefix;
+    ate_id "(ate_id)": extract_ate_id;
+    serial_number "(serial_number)": is_dummy_device;
+}
+
+#[pymodule]
+fn field_transformer_native(_py: Python, module: &PyModule) -> PyResult<()> {
+    add_ports(module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn py_error(type_name: &str, message: &str) -> TransformError {
+        TransformError::Python {
+            type_name: type_name.into(),
+            message: message.into(),
+            traceback: String::new(),
+        }
+    }
+
+    /// Evaluate `expr` with the ports bound to `native` and `d` to
+    /// 2018-01-01 08:02:02.123.
+    fn eval<T>(expr: &str, check: impl FnOnce(Python, PyResult<&PyAny>) -> T) -> T {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "field_transformer_native").unwrap();
+            add_ports(module).unwrap();
+            let globals = PyDict::new(py);
+            globals
+                .set_item("__builtins__", py.import("builtins").unwrap())
+                .unwrap();
+            globals.set_item("native", module).unwrap();
+            py.run(
+                "import datetime\nd = datetime.datetime(2018, 1, 1, 8, 2, 2, 123000)",
+                Some(globals),
+                None,
+            )
+            .unwrap();
+            check(py, py.eval(expr, Some(globals), None))
+        })
+    }
+
+    fn repr(expr: &str) -> String {
+        eval(expr, |_, result| {
+            result.unwrap().repr().unwrap().to_str().unwrap().to_owned()
+        })
+    }
+
+    /// The type name and `str()` of an exception.
+    fn describe(py: Python, err: PyErr) -> (String, String) {
+        let value = err.instance(py);
+        (
+            value.get_type().name().unwrap().to_owned(),
+            value.str().unwrap().to_str().unwrap().to_owned(),
+        )
+    }
+
+    /// What `expr` raises.
+    fn raised(expr: &str) -> (String, String) {
+        eval(expr, |py, result| describe(py, result.unwrap_err()))
+    }
+
+    /// What `err` raises in Python.
+    fn mapped(err: TransformError) -> (String, String) {
+        eval("None", |py, _| describe(py, raise(py, err)))
+    }
+
+    #[test]
+    fn datetimes_take_the_ports_python_would_branch_to() {
+        assert_eq!(repr("native.to_date_index(d)"), "20180101");
+        assert_eq!(repr("native.to_date_index({'$date': d})"), "20180101");
+        assert_eq!(
+            repr("native.bson_date_to_datetime({'$date': d})"),
+            "datetime.datetime(2018, 1, 1, 8, 2, 2, 123000)"
+        );
+        assert_eq!(
+            repr("native.bson_date_to_datetime({'$date': '2018-01-01T08:02:02.123Z'})"),
+            "datetime.datetime(2018, 1, 1, 8, 2, 2, 123000)"
+        );
+        // Other methods see the isoformat string, which the
+        // `datetime_to_*` ports accept.
+        assert_eq!(repr("native.datetime_to_date_index(d)"), "20180101");
+        assert_eq!(
+            repr("native.convert_bson_object_id(d)"),
+            "'2018-01-01T08:02:02.123000'"
+        );
+    }
+
+    #[test]
+    fn results_keep_their_python_types() {
+        assert_eq!(
+            repr("native.extract_ate_id('DW.MM.1-FLEX.2.ZH')"),
+            "('DW', '1', 'FLEX', 2, 'ZH')"
+        );
+        assert_eq!(repr("native.convert_bson_object_id({'x': 1})"), "None");
+    }
+
+    #[test]
+    fn keep_zero_microsecond_is_tested_for_truth() {
+        let value = "'2018-01-01T08:02:02.500000'";
+        for (keep, expected) in [
+            ("", "'2018-01-01 08:02:02.500000'"),
+            (", 1", "'2018-01-01 08:02:02.500000'"),
+            (", 0", "'2018-01-01 08:02:02'"),
+            (", []", "'2018-01-01 08:02:02'"),
+            (", keep_zero_microsecond=None", "'2018-01-01 08:02:02'"),
+        ] {
+            let expr = format!("native.datetime_to_db_datetime({}{})", value, keep);
+            assert_eq!(repr(&expr), expected, "{}", expr);
+        }
+    }
+
+    #[test]
+    fn arguments_that_do_not_convert_raise_type_error() {
+        assert_eq!(
+            raised("native.to_date_index({'$date': 1 << 70})"),
+            (
+                "TypeError".into(),
+                "integer out of 64-bit range (int)".into()
+            )
+        );
+        assert_eq!(raised("native.extract_ate_id()").0, "TypeError");
+    }
+
+    #[test]
+    fn errors_raise_the_python_exception() {
+        assert_eq!(
+            raised("native.normalize_second_timestamp('x')"),
+            (
+                "ValueError".into(),
+                "could not convert string to float: 'x'".into()
+            )
+        );
+        assert_eq!(
+            mapped(py_error("KeyError", "'$date'")),
+            ("KeyError".into(), "'$date'".into())
+        );
+        assert_eq!(
+            eval("None", |py, _| {
+                let err = raise(py, py_error("KeyError", "'$date'"));
+                err.instance(py)
+                    .getattr("args")
+                    .unwrap()
+                    .repr()
+                    .unwrap()
+                    .to_string()
+            }),
+            "('$date',)"
+        );
+        assert_eq!(
+            mapped(py_error("ParseError", "Unable to parse")),
+            ("ValueError".into(), "Unable to parse".into())
+        );
+        assert_eq!(
+            mapped(py_error("UnknownError", "boom")),
+            ("RuntimeError".into(), "UnknownError: boom".into())
+        );
+        // Names of builtins that are not exceptions don't become one.
+        assert_eq!(
+            mapped(py_error("len", "boom")),
+            ("RuntimeError".into(), "len: boom".into())
+        );
+        assert_eq!(
+            mapped(TransformError::Conversion("bad value".into())),
+            ("TypeError".into(), "bad value".into())
+        );
+        assert_eq!(mapped(TransformError::MissingOutput).0, "RuntimeError");
+    }
+}
@@ -0,0 +1,190 @@
+//! Per-record latency and throughput of representative transforms on each
+//! execution path:
+//!
+//! * `pyo3`: `Engine` with native transforms off, so every call takes the
+//!   GIL, converts the record to Python and the result back, and runs the
+//!   FieldTransformer method;
+//! * `rustpython`: `RustPythonEngine` with native transforms off, when
+//!   built with `--features rustpython`;
+//! * `native`: `Engine` as deployed, answering from the Rust port where
+//!   there is one. For `transform_with_custom_code` it runs what the
+//!   snippet does with the ports, for comparison.
+//!
+//! Each path is timed on one record (`record`, the per-record latency) and
+//! on `BATCH` records (`batch`, reported as records per second).
+//! `pyo3_overhead` times what the `pyo3` path spends around the method:
+//! taking the GIL and converting a record each way.
+//!
+//! ```sh
+//! cargo bench --bench transforms --features rustpython
+//! ```
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use pyo3::Python;
+use pyo3_tests::{convert, native, Engine, ScriptBackend};
+use serde_json::{json, Map, Value};
+
+/// Records per `batch` iteration.
+const BATCH: usize = 1_000;
+
+/// `transform_with_custom_code` snippet: the factory of an ATE id.
+const SNIPPET: &str = "output = field_transformer.extract_ate_id(value)[2]";
+
+/// A backend under test and the name of its path.
+type Path = (&'static str, Box<dyn ScriptBackend>);
+
+fn paths() -> Vec<Path> {
+    #[cfg_attr(not(feature = "rustpython"), allow(unused_mut))]
+    let mut paths: Vec<Path> = vec![(
+        "pyo3",
+        Box::new(
+            Engine::builder()
+                .native_transforms(false)
+                .build()
+                .expect("engine starts"),
+        ),
+    )];
+    #[cfg(feature = "rustpython")]
+    paths.push((
+        "rustpython",
+        Box::new(
+            pyo3_tests::RustPythonEngine::builder()
+                .native_transforms(false)
+                .build()
+                .expect("engine starts"),
+        ),
+    ));
+    paths
+}
+
+/// `count` inputs cycling through `samples`, as a batch would see them.
+fn batch(samples: &[Value], count: usize) -> Vec<Value> {
+    samples.iter().cycle().take(count).cloned().collect()
+}
+
+fn bench_method(c: &mut Criterion, paths: &[Path], method: &str, samples: &[Value]) {
+    let params = Map::new();
+    let records = batch(samples, BATCH);
+    let deployed = Engine::new().expect("engine starts");
+    let native = native::lookup(method).map(|_| &deployed as &dyn ScriptBackend);
+
+    let mut group = c.benchmark_group(method);
+    let backends = paths
+        .iter()
+        .map(|(name, backend)| (*name, backend.as_ref()))
+        .chain(native.map(|backend| ("native", backend)));
+    for (name, backend) in backends {
+        group.throughput(Throughput::Elements(1));
+        group.bench_function(format!("{}/record", name), |b| {
+            b.iter(|| backend.transform(black_box(&samples[0]), method, &params))
+        });
+        group.throughput(Throughput::Elements(records.len() as u64));
+        group.bench_function(format!("{}/batch", name), |b| {
+            b.iter(|| {
+                for record in &records {
+                    let _ = black_box(backend.transform(record, method, &params));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn normalize_version(c: &mut Criterion) {
+    let samples = [
+        json!("4.5.2"),
+        json!("2.10"),
+        json!("3"),
+        json!("1.0.12-debug"),
+    ];
+    bench_method(c, &paths(), "normalize_version", &samples);
+}
+
+fn to_date_index(c: &mut Criterion) {
+    let samples = [
+        json!({"$date": "2018-01-01T02:02:02.123Z"}),
+        json!({"$date": 1514772122123i64}),
+        json!("2021-06-30T23:59:59.000001"),
+        json!("2019-12-31T16:30:00.250000Z"),
+    ];
+    bench_method(c, &paths(), "to_date_index", &samples);
+}
+
+fn transform_with_custom_code(c: &mut Criterion) {
+    let samples = [
+        json!("DW.MM.1-Flex.1.ZH"),
+        json!("SW.RF.2.12-VN.HN"),
+        json!("DW.2.5.Flex007"),
+    ];
+    let records = batch(&samples, BATCH);
+    let paths = paths();
+
+    let mut group = c.benchmark_group("transform_with_custom_code");
+    for (name, backend) in &paths {
+        group.throughput(Throughput::Elements(1));
+        group.bench_function(format!("{}/record", name), |b| {
+            b.iter(|| backend.transform_with_custom_code(black_box(&samples[0]), SNIPPET))
+        });
+        group.throughput(Throughput::Elements(records.len() as u64));
+        group.bench_function(format!("{}/batch", name), |b| {
+            b.iter(|| {
+                for record in &records {
+                    let _ = black_box(backend.transform_with_custom_code(record, SNIPPET));
+                }
+            })
+        });
+    }
+
+    let extract_ate_id = native::lookup("extract_ate_id").expect("extract_ate_id is ported");
+    let snippet = |value: &Value| extract_ate_id(value, &Map::new()).map(|id| id.get(2).cloned());
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("native/record", |b| {
+        b.iter(|| snippet(black_box(&samples[0])))
+    });
+    group.throughput(Throughput::Elements(records.len() as u64));
+    group.bench_function("native/batch", |b| {
+        b.iter(|| {
+            for record in &records {
+                let _ = black_box(snippet(record));
+            }
+        })
+    });
+    group.finish();
+}
+
+fn pyo3_overhead(c: &mut Criterion) {
+    // Starts the interpreter.
+    let _engine = Engine::new().expect("engine starts");
+    let record = json!({
+        "_id": {"$oid": "5a4a9b1a2f4c3b0001a1b2c3"},
+        "serial_number": "W0DFA12345",
+        "firmware": "1.0.12-debug",
+        "created_at": {"$date": "2018-01-01T02:02:02.123Z"},
+        "steps": [1250, 980, 4410],
+        "synced": true,
+    });
+
+    let mut group = c.benchmark_group("pyo3_overhead");
+    group.bench_function("gil", |b| {
+        b.iter(|| Python::with_gil(|py| black_box(py).None()))
+    });
+    Python::with_gil(|py| {
+        let object = convert::to_py(py, &record);
+        group.bench_function("to_py", |b| {
+            b.iter(|| convert::to_py(py, black_box(&record)))
+        });
+        group.bench_function("from_py", |b| {
+            b.iter(|| convert::from_py(black_box(object.as_ref(py))))
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    normalize_version,
+    to_date_index,
+    transform_with_custom_code,
+    pyo3_overhead
+);
+criterion_main!(benches);
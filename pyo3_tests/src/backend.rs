@@ -0,0 +1,149 @@
+//! Interpreter-agnostic interface to a FieldTransformer host.
+//!
+//! `Engine` embeds CPython through PyO3 (feature `cpython`, on by default);
+//! `RustPythonEngine` embeds RustPython with a frozen standard library
+//! (feature `rustpython`), so a build with
+//! `--no-default-features --features rustpython` links no libpython at all.
+//! Code that only needs to run transforms should take a
+//! `&dyn ScriptBackend` and let the caller pick with `BackendKind`.
+
+use std::{fmt, str::FromStr, sync::Arc};
+
+use serde_json::{Map, Value};
+
+use crate::{error::TransformError, loader::FieldTransformerSource};
+
+/// A Python interpreter with the FieldTransformer module loaded.
+pub trait ScriptBackend {
+    fn kind(&self) -> BackendKind;
+
+    /// Replace the FieldTransformer module that methods and snippets run
+    /// against.
+    fn load_module(&mut self, source: &FieldTransformerSource) -> Result<(), TransformError>;
+
+    /// Compile `custom_code` once, so `call` can run it over many records.
+    fn compile(&self, custom_code: &str) -> Result<Snippet, TransformError>;
+
+    /// Run a compiled snippet with `value` bound to the `value` variable
+    /// and return whatever it assigned to `output`.
+    fn call(&self, snippet: &Snippet, value: &Value) -> Result<Value, TransformError>;
+
+    /// Call `FieldTransformer.transform(value, method_name, **params)`.
+    fn transform(
+        &self,
+        value: &Value,
+        method_name: &str,
+        params: &Map<String, Value>,
+    ) -> Result<Value, TransformError>;
+
+    fn transform_with_custom_code(
+        &self,
+        value: &Value,
+        custom_code: &str,
+    ) -> Result<Value, TransformError> {
+        let snippet = self.compile(custom_code)?;
+        self.call(&snippet, value)
+    }
+}
+
+/// Handle to a snippet compiled by a `ScriptBackend`.
+///
+/// Backends keep the code objects in their own cache; the handle carries
+/// the source too, so a snippet evicted from the cache is simply compiled
+/// again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    pub(crate) source: Arc<str>,
+}
+
+impl Snippet {
+    pub(crate) fn new(source: &str) -> Self {
+        Snippet {
+            source: source.into(),
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+/// Interpreter a `ScriptBackend` runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BackendKind {
+    Pyo3,
+    RustPython,
+}
+
+impl BackendKind {
+    /// Backends compiled into this build, preferred one first.
+    pub fn available() -> Vec<BackendKind> {
+        let mut kinds = Vec::new();
+        if cfg!(feature = "cpython") {
+            kinds.push(BackendKind::Pyo3);
+        }
+        if cfg!(feature = "rustpython") {
+            kinds.push(BackendKind::RustPython);
+        }
+        kinds
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackendKind::Pyo3 => "pyo3",
+            BackendKind::RustPython => "rustpython",
+        }
+    }
+
+    /// Build a backend of this kind with its default settings.
+    pub fn build(
+        self,
+        source: &FieldTransformerSource,
+    ) -> Result<Box<dyn ScriptBackend>, TransformError> {
+        match self {
+            #[cfg(feature = "cpython")]
+            BackendKind::Pyo3 => Ok(Box::new(crate::engine::Engine::load(source)?)),
+            #[cfg(feature = "rustpython")]
+            BackendKind::RustPython => Ok(Box::new(
+                crate::rustpython::RustPythonEngine::builder()
+                    .source(source.clone())
+                    .build()?,
+            )),
+            #[allow(unreachable_patterns)]
+            kind => Err(TransformError::Load(format!(
+                "backend '{}' is not compiled into this build",
+                kind
+            ))),
+        }
+    }
+}
+
+impl Default for BackendKind {
+    fn default() -> Self {
+        BackendKind::available()
+            .first()
+            .copied()
+            .unwrap_or(BackendKind::Pyo3)
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pyo3" | "cpython" => Ok(BackendKind::Pyo3),
+            "rustpython" => Ok(BackendKind::RustPython),
+            _ => Err(format!(
+                "unknown backend '{}', expected 'pyo3' or 'rustpython'",
+                s
+            )),
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! `field-transform`: apply a spec or a custom-code snippet to NDJSON.
+
+use std::{
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::PathBuf,
+    process,
+};
+
+use pyo3_tests::{
+    native::{brand::BrandRegistry, serial::TrackerPrefixes},
+    Applied, BackendKind, DeadLetter, DeadLetterSink, ErrorPolicy, FieldTransformerSource,
+    ScriptBackend, Snippet, Spec, TransformError,
+};
+use serde_json::Value;
+
+const USAGE: &str =
+    "usage: field-transform (-s SPEC | -c CODE | --code-file FILE) [OPTIONS] [INPUT]...
+
+Reads newline-delimited JSON records from each INPUT (stdin when none is
+given, or for `-`), transforms every record and writes the results to
+stdout, one JSON document per line.
+
+  -s, --spec FILE       transformation spec (JSON if it ends in .json, YAML otherwise)
+  -c, --code CODE       custom-code snippet, run with `value` bound to the record
+      --code-file FILE  read the snippet from FILE
+  -b, --backend NAME    pyo3 or rustpython (default: the first compiled in);
+                        rustpython enforces no timeout or operation budget
+  -m, --module FILE     FieldTransformer module to load instead of the embedded test
+                        copy (test.py)
+      --brands FILE     brand registry for the native brand transforms (JSON if it
+                        ends in .json, YAML otherwise)
+      --tracker-prefixes FILE
+                        tracker serial-number prefixes for the native serial-number
+                        transforms, one per line
+      --strict          stop at the first failure, exiting with status 1; overrides
+                        the spec's on_error policies with abort-batch
+  -e, --errors FILE     write dead letters to FILE instead of stderr
+
+Every failure is reported as a dead letter: one JSON line holding the
+original record, the field, method and params that failed, the error and
+where the record was read from. What happens to the record is up to the
+field's on_error policy (fail-record, null-field, keep-original or
+abort-batch); records that fail a custom-code snippet are dropped.";
+
+enum Transform {
+    Spec(Spec),
+    Code(Snippet),
+}
+
+struct Options {
+    spec: Option<PathBuf>,
+    code: Option<String>,
+    code_file: Option<PathBuf>,
+    backend: BackendKind,
+    module: Option<PathBuf>,
+    brands: Option<PathBuf>,
+    tracker_prefixes: Option<PathBuf>,
+    strict: bool,
+    errors: Option<PathBuf>,
+    inputs: Vec<String>,
+}
+
+fn main() {
+    let options = parse_args();
+    match run(&options) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(err) => {
+            eprintln!("field-transform: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        spec: None,
+        code: None,
+        code_file: None,
+        backend: BackendKind::default(),
+        module: None,
+        brands: None,
+        tracker_prefixes: None,
+        strict: false,
+        errors: None,
+        inputs: Vec::new(),
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| exit_usage());
+        match arg.as_str() {
+            "-s" | "--spec" => options.spec = Some(value().into()),
+            "-c" | "--code" => options.code = Some(value()),
+            "--code-file" => options.code_file = Some(value().into()),
+            "-b" | "--backend" => {
+                options.backend = value().parse().unwrap_or_else(|err| {
+                    eprintln!("field-transform: {}", err);
+                    process::exit(2);
+                })
+            }
+            "-m" | "--module" => options.module = Some(value().into()),
+            "--brands" => options.brands = Some(value().into()),
+            "--tracker-prefixes" => options.tracker_prefixes = Some(value().into()),
+            "--strict" => options.strict = true,
+            "-e" | "--errors" => options.errors = Some(value().into()),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') && arg != "-" => exit_usage(),
+            _ => options.inputs.push(arg),
+        }
+    }
+
+    let transforms = [
+        options.spec.is_some(),
+        options.code.is_some(),
+        options.code_file.is_some(),
+    ];
+    if transforms.iter().filter(|given| **given).count() != 1 {
+        exit_usage();
+    }
+    if options.inputs.is_empty() {
+        options.inputs.push("-".to_owned());
+    }
+    options
+}
+
+fn exit_usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+/// Transform every input; false when a record failed under `--strict`.
+fn run(options: &Options) -> Result<bool, Box<dyn std::error::Error>> {
+    let source = match &options.module {
+        Some(path) => FieldTransformerSource::Path(path.clone()),
+        None => FieldTransformerSource::EmbeddedTestCopy,
+    };
+    if let Some(path) = &options.brands {
+        BrandRegistry::load(path)?.install();
+    }
+    if let Some(path) = &options.tracker_prefixes {
+        TrackerPrefixes::load(path)?.install();
+    }
+    let backend = options.backend.build(&source)?;
+    let transform = match (&options.spec, &options.code, &options.code_file) {
+        (Some(path), _, _) => {
+            let mut spec = Spec::load(path)?;
+            if options.strict {
+                spec.set_policy(ErrorPolicy::AbortBatch);
+            }
+            Transform::Spec(spec)
+        }
+        (_, Some(code), _) => Transform::Code(backend.compile(code)?),
+        (_, _, Some(path)) => {
+            let code = std::fs::read_to_string(path)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+            Transform::Code(backend.compile(&code)?)
+        }
+        _ => unreachable!("parse_args requires one transform"),
+    };
+
+    let errors: Box<dyn Write> = match &options.errors {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).map_err(|err| format!("{}: {}", path.display(), err))?,
+        )),
+        None => Box::new(io::stderr().lock()),
+    };
+    let mut dead_letters = DeadLetterSink::new(errors);
+    let mut out = BufWriter::new(io::stdout().lock());
+
+    for input in &options.inputs {
+        let reader: Box<dyn BufRead> = if input == "-" {
+            Box::new(io::stdin().lock())
+        } else {
+            Box::new(BufReader::new(
+                File::open(input).map_err(|err| format!("{}: {}", input, err))?,
+            ))
+        };
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line.map_err(|err| format!("{}: {}", input, err))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let outcome = match serde_json::from_str::<Value>(&line) {
+                Ok(record) => apply(&transform, backend.as_ref(), record, options.strict),
+                Err(err) => {
+                    let error = TransformError::Conversion(format!("invalid JSON: {}", err));
+                    rejected(
+                        DeadLetter::new(Value::String(line.clone()), error),
+                        options.strict,
+                    )
+                }
+            };
+
+            for letter in outcome.dead_letters {
+                dead_letters.write(&letter.at(input, index + 1))?;
+            }
+            if let Some(output) = outcome.output {
+                serde_json::to_writer(&mut out, &output)?;
+                out.write_all(b"\n")?;
+            }
+            if outcome.abort {
+                out.flush()?;
+                dead_letters.flush()?;
+                return Ok(false);
+            }
+        }
+    }
+
+    out.flush()?;
+    dead_letters.flush()?;
+    Ok(true)
+}
+
+/// What a record turned into.
+struct Outcome {
+    output: Option<Value>,
+    dead_letters: Vec<DeadLetter>,
+    abort: bool,
+}
+
+impl From<Applied> for Outcome {
+    fn from(applied: Applied) -> Self {
+        Outcome {
+            output: applied.output.map(Value::Object),
+            dead_letters: applied.dead_letters,
+            abort: applied.abort,
+        }
+    }
+}
+
+fn apply(
+    transform: &Transform,
+    backend: &dyn ScriptBackend,
+    record: Value,
+    strict: bool,
+) -> Outcome {
+    match transform {
+        Transform::Spec(spec) => spec.apply(backend, &record).into(),
+        Transform::Code(snippet) => match backend.call(snippet, &record) {
+            Ok(output) => Outcome {
+                output: Some(output),
+                dead_letters: Vec::new(),
+                abort: false,
+            },
+            Err(error) => rejected(DeadLetter::new(record, error), strict),
+        },
+    }
+}
+
+/// A record dropped as a whole.
+fn rejected(letter: DeadLetter, strict: bool) -> Outcome {
+    let policy = if strict {
+        ErrorPolicy::AbortBatch
+    } else {
+        ErrorPolicy::FailRecord
+    };
+
+    Outcome {
+        output: None,
+        dead_letters: vec![DeadLetter {
+            policy: Some(policy),
+            ..letter
+        }],
+        abort: strict,
+    }
+}
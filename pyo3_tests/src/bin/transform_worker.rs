@@ -0,0 +1,7 @@
+//! Pool worker: serves `pyo3_tests::worker` requests on stdin/stdout.
+
+use std::io;
+
+fn main() -> io::Result<()> {
+    pyo3_tests::worker::serve(io::stdin().lock(), io::stdout().lock())
+}
@@ -0,0 +1,109 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// Hash of a snippet's source, as used by `CodeCache`.
+pub fn key(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+struct Entry<C> {
+    source: String,
+    code: C,
+    last_used: u64,
+}
+
+/// LRU cache of compiled snippets keyed by a hash of their source.
+///
+/// Unlike `FieldTransformer.CUSTOM_CODE`, which keys on `id(custom_code)`,
+/// two snippets with the same text share one code object no matter where
+/// the string came from, and a hash collision is caught by comparing the
+/// stored source.
+pub(crate) struct CodeCache<C> {
+    capacity: usize,
+    entries: HashMap<u64, Entry<C>>,
+    clock: u64,
+}
+
+impl<C: Clone> CodeCache<C> {
+    pub fn new(capacity: usize) -> Self {
+        CodeCache {
+            capacity,
+            entries: HashMap::with_capacity(capacity),
+            clock: 0,
+        }
+    }
+
+    /// Return the code object for `source`, compiling it with `compile` on
+    /// a miss.
+    #[cfg(feature = "rustpython")]
+    pub fn get_or_compile<E, F>(&mut self, source: &str, compile: F) -> Result<C, E>
+    where
+        F: FnOnce(u64) -> Result<C, E>,
+    {
+        if let Some(code) = self.get(source) {
+            return Ok(code);
+        }
+
+        let code = compile(key(source))?;
+        self.insert(source, code.clone());
+        Ok(code)
+    }
+
+    /// The code object for `source`, if it is cached.
+    pub fn get(&mut self, source: &str) -> Option<C> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(&key(source))?;
+        if entry.source != source {
+            return None;
+        }
+        entry.last_used = self.clock;
+        Some(entry.code.clone())
+    }
+
+    /// Cache `code` as the code object for `source`.
+    pub fn insert(&mut self, source: &str, code: C) {
+        self.clock += 1;
+        let key = key(source);
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            self.evict();
+        }
+        self.entries.insert(
+            key,
+            Entry {
+                source: source.to_owned(),
+                code,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[cfg(feature = "rustpython")]
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn evict(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| *key);
+
+        if let Some(key) = oldest {
+            self.entries.remove(&key);
+        }
+    }
+}
@@ -0,0 +1,273 @@
+#[cfg(feature = "cpython")]
+use pyo3::{prelude::*, types::PyModule};
+
+pub const DEFAULT_OUTPUT_LIMIT: usize = 64 * 1024;
+
+pub(crate) const CAPTURE_CODE: &str = r#"
+import io
+
+
+class CappedWriter(io.TextIOBase):
+    def __init__(self, limit):
+        self.limit = limit
+        self.parts = []
+        self.size = 0
+        self.truncated = False
+
+    def writable(self):
+        return True
+
+    def write(self, text):
+        if not isinstance(text, str):
+            raise TypeError(f"write() argument must be str, not {type(text).__name__}")
+        written = len(text)
+        room = self.limit - self.size
+        if written > room:
+            text = text[:max(room, 0)]
+            self.truncated = True
+        if text:
+            self.parts.append(text)
+            self.size += len(text)
+        return written
+
+    def getvalue(self):
+        return "".join(self.parts)
+"#;
+
+/// Routes `sys.stdout`/`sys.stderr` to the writers of the capture active in
+/// the current thread, or to the host's streams when there is none. The
+/// streams are swapped for dispatchers once rather than per call, so calls
+/// on other threads neither write into nor restore each other's writers.
+#[cfg(feature = "cpython")]
+const DISPATCH_CODE: &str = r#"
+import contextvars
+import sys
+
+_writers = contextvars.ContextVar("writers", default=None)
+
+
+class Dispatcher:
+    def __init__(self, name, host):
+        self._name = name
+        self._host = host
+
+    def _target(self):
+        writers = _writers.get()
+        return self._host if writers is None else writers[self._name]
+
+    def write(self, text):
+        return self._target().write(text)
+
+    def flush(self):
+        target = self._target()
+        if target is not None:
+            target.flush()
+
+    def __getattr__(self, name):
+        return getattr(self._host, name)
+
+
+def start(limit):
+    for name in ("stdout", "stderr"):
+        if not isinstance(getattr(sys, name), Dispatcher):
+            setattr(sys, name, Dispatcher(name, getattr(sys, name)))
+    writers = {"stdout": CappedWriter(limit), "stderr": CappedWriter(limit)}
+    return writers, _writers.set(writers)
+
+
+def stop(token):
+    _writers.reset(token)
+"#;
+
+/// Module the capture code is registered as, shared by every engine so the
+/// dispatchers are installed once per process.
+#[cfg(feature = "cpython")]
+const CAPTURE_MODULE: &str = "_field_transformer_capture";
+
+/// Text a call wrote to `sys.stdout` and `sys.stderr`, next to its result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Captured<T> {
+    pub result: T,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether either stream hit the output limit and lost text.
+    pub truncated: bool,
+}
+
+impl<T> Captured<T> {
+    /// A result produced without running any Python, so nothing to capture.
+    pub(crate) fn silent(result: T) -> Self {
+        Captured {
+            result,
+            stdout: String::new(),
+            stderr: String::new(),
+            truncated: false,
+        }
+    }
+}
+
+/// Sends what a call writes to `sys.stdout`/`sys.stderr` to capped
+/// in-memory writers, so custom code cannot write into the host's own
+/// output stream. Calls on different threads capture separately.
+#[cfg(feature = "cpython")]
+pub(crate) struct Capture {
+    start: PyObject,
+    stop: PyObject,
+    limit: usize,
+}
+
+#[cfg(feature = "cpython")]
+impl Capture {
+    pub fn new(py: Python, limit: usize) -> PyResult<Self> {
+        let modules = py.import("sys")?.getattr("modules")?;
+        let module = match modules.get_item(CAPTURE_MODULE) {
+            Ok(module) => module,
+            Err(_) => {
+                let code = format!("{}{}", CAPTURE_CODE, DISPATCH_CODE);
+                PyModule::from_code(py, &code, "<capture>", CAPTURE_MODULE)?.into()
+            }
+        };
+
+        Ok(Capture {
+            start: module.getattr("start")?.into(),
+            stop: module.getattr("stop")?.into(),
+            limit,
+        })
+    }
+
+    pub fn run<T, F>(&self, py: Python, f: F) -> PyResult<Captured<T>>
+    where
+        F: FnOnce() -> T,
+    {
+        let (writers, token): (&PyAny, &PyAny) =
+            self.start.as_ref(py).call1((self.limit,))?.extract()?;
+        let result = f();
+        self.stop.call1(py, (token,))?;
+
+        let read = |name: &str| -> PyResult<(String, bool)> {
+            let writer = writers.get_item(name)?;
+            Ok((
+                writer.call_method0("getvalue")?.extract()?,
+                writer.getattr("truncated")?.extract()?,
+            ))
+        };
+        let (stdout, stdout_truncated) = read("stdout")?;
+        let (stderr, stderr_truncated) = read("stderr")?;
+
+        Ok(Captured {
+            result,
+            stdout,
+            stderr,
+            truncated: stdout_truncated || stderr_truncated,
+        })
+    }
+}
+
+/// Forward captured output to the `log` facade and return the result.
+pub(crate) fn log_output<T>(captured: Captured<T>) -> T {
+    if !captured.stdout.is_empty() {
+        log::info!(target: "field_transformer::stdout", "{}", captured.stdout.trim_end());
+    }
+    if !captured.stderr.is_empty() {
+        log::warn!(target: "field_transformer::stderr", "{}", captured.stderr.trim_end());
+    }
+    if captured.truncated {
+        log::warn!("custom code output was truncated");
+    }
+
+    captured.result
+}
+
+#[cfg(all(test, feature = "cpython"))]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::engine::Engine;
+
+    fn capture(limit: usize, code: &str) -> Captured<PyResult<()>> {
+        Python::with_gil(|py| {
+            Capture::new(py, limit)
+                .unwrap()
+                .run(py, || py.run(code, None, None))
+                .unwrap()
+        })
+    }
+
+    #[test]
+    fn streams_are_captured_separately() {
+        let captured = capture(
+            100,
+            "import sys\nprint('out')\nprint('err', file=sys.stderr)",
+        );
+        assert!(captured.result.is_ok());
+        assert_eq!(captured.stdout, "out\n");
+        assert_eq!(captured.stderr, "err\n");
+        assert!(!captured.truncated);
+    }
+
+    #[test]
+    fn output_past_the_limit_is_dropped() {
+        let captured = capture(8, "print('hello'); print('world'); print('again')");
+        assert_eq!(captured.stdout, "hello\nwo");
+        assert!(captured.truncated);
+    }
+
+    #[test]
+    fn the_limit_counts_characters() {
+        let captured = capture(3, "print('日本語', end='')");
+        assert_eq!(captured.stdout, "日本語");
+        assert!(!captured.truncated);
+    }
+
+    #[test]
+    fn writing_bytes_is_a_type_error() {
+        let captured = capture(100, "import sys\nsys.stdout.write(b'x')");
+        let message = Python::with_gil(|py| {
+            let err = captured.result.unwrap_err();
+            assert!(err.is_instance::<pyo3::exceptions::PyTypeError>(py));
+            err.pvalue(py).to_string()
+        });
+        assert_eq!(message, "write() argument must be str, not bytes");
+    }
+
+    #[test]
+    fn nothing_is_captured_between_calls() {
+        capture(100, "print('inside')");
+        let active = Python::with_gil(|py| {
+            py.import(CAPTURE_MODULE)
+                .unwrap()
+                .getattr("_writers")
+                .unwrap()
+                .call_method0("get")
+                .unwrap()
+                .is_none()
+        });
+        assert!(active);
+    }
+
+    #[test]
+    fn threads_capture_their_own_output() {
+        let engine = Arc::new(Engine::new().unwrap());
+        let code = "for i in range(20000):\n    print('t' + str(value))\noutput = value";
+        let threads: Vec<_> = (0..4)
+            .map(|thread| {
+                let engine = Arc::clone(&engine);
+                thread::spawn(move || engine.run_custom_code(&json!(thread), code))
+            })
+            .collect();
+
+        for (thread, handle) in threads.into_iter().enumerate() {
+            let captured = handle.join().unwrap();
+            assert_eq!(captured.result, Ok(json!(thread)));
+            assert!(
+                captured.stdout == format!("t{}\n", thread).repeat(20000),
+                "thread {} captured output of another thread",
+                thread
+            );
+            assert!(!captured.truncated);
+        }
+    }
+}
@@ -0,0 +1,281 @@
+//! Conversion between `serde_json::Value` and Python objects.
+//!
+//! JSON maps onto Python the way `json.loads` would: objects become `dict`,
+//! arrays `list`, integers `int` (the full i64 and u64 ranges), other numbers
+//! `float`, plus `bool`, `str` and `None`.
+//!
+//! Going back, results that are not plain JSON get a fixed mapping:
+//!
+//! * `tuple`, `set` and `frozenset` become arrays, so
+//!   `extract_ate_id` yields `[category, station, factory, number, city]`;
+//! * `datetime`, `date` and `time` become their `isoformat()` string, e.g.
+//!   `bson_date_to_datetime` yields `"2018-01-01T02:02:02.123400"`;
+//! * `timedelta` becomes its `total_seconds()` as a float;
+//! * `int` and `bool` dict keys are stringified like `json.dumps` does.
+//!
+//! Integers outside the u64/i64 range, NaN/infinite floats and any other type
+//! are reported as `TransformError::Conversion`.
+
+use pyo3::{
+    prelude::*,
+    types::{PyBool, PyDict, PyFloat, PyFrozenSet, PyList, PyLong, PySet, PyString, PyTuple},
+};
+use serde_json::{Map, Number, Value};
+
+use crate::error::TransformError;
+
+pub fn to_py(py: Python, value: &Value) -> PyObject {
+    match value {
+        Value::Null => py.None(),
+        Value::Bool(b) => b.to_object(py),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                i.to_object(py)
+            } else if let Some(u) = n.as_u64() {
+                u.to_object(py)
+            } else {
+                n.as_f64().unwrap_or(f64::NAN).to_object(py)
+            }
+        }
+        Value::String(s) => s.to_object(py),
+        Value::Array(items) => PyList::new(py, items.iter().map(|item| to_py(py, item))).into(),
+        Value::Object(map) => {
+            let dict = PyDict::new(py);
+            for (key, item) in map {
+                // Setting a str key on a fresh dict cannot fail.
+                let _ = dict.set_item(key, to_py(py, item));
+            }
+            dict.into()
+        }
+    }
+}
+
+pub fn from_py(value: &PyAny) -> Result<Value, TransformError> {
+    if value.is_none() {
+        return Ok(Value::Null);
+    }
+    if let Ok(b) = value.downcast::<PyBool>() {
+        return Ok(Value::Bool(b.is_true()));
+    }
+    if value.downcast::<PyLong>().is_ok() {
+        if let Ok(i) = value.extract::<i64>() {
+            return Ok(Value::from(i));
+        }
+        if let Ok(u) = value.extract::<u64>() {
+            return Ok(Value::from(u));
+        }
+        return Err(conversion_error(value, "integer out of 64-bit range"));
+    }
+    if let Ok(f) = value.downcast::<PyFloat>() {
+        return Number::from_f64(f.value())
+            .map(Value::Number)
+            .ok_or_else(|| conversion_error(value, "float is not finite"));
+    }
+    if let Ok(s) = value.downcast::<PyString>() {
+        return s
+            .to_str()
+            .map(|s| Value::String(s.to_owned()))
+            .map_err(|_| conversion_error(value, "string is not valid UTF-8"));
+    }
+    if let Ok(dict) = value.downcast::<PyDict>() {
+        let mut map = Map::with_capacity(dict.len());
+        for (key, item) in dict.iter() {
+            map.insert(dict_key(key)?, from_py(item)?);
+        }
+        return Ok(Value::Object(map));
+    }
+    if let Ok(list) = value.downcast::<PyList>() {
+        return list
+            .iter()
+            .map(from_py)
+            .collect::<Result<_, _>>()
+            .map(Value::Array);
+    }
+    if let Ok(tuple) = value.downcast::<PyTuple>() {
+        return tuple
+            .iter()
+            .map(from_py)
+            .collect::<Result<_, _>>()
+            .map(Value::Array);
+    }
+    if value.downcast::<PySet>().is_ok() || value.downcast::<PyFrozenSet>().is_ok() {
+        let items = value
+            .iter()
+            .map_err(|_| conversion_error(value, "set is not iterable"))?;
+        return items
+            .map(|item| {
+                item.map_err(|_| conversion_error(value, "set iteration failed"))
+                    .and_then(from_py)
+            })
+            .collect::<Result<_, _>>()
+            .map(Value::Array);
+    }
+    if has_method(value, "isoformat") {
+        return value
+            .call_method0("isoformat")
+            .and_then(|s| s.extract::<String>())
+            .map(Value::String)
+            .map_err(|_| conversion_error(value, "isoformat() failed"));
+    }
+    if has_method(value, "total_seconds") {
+        return value
+            .call_method0("total_seconds")
+            .and_then(|s| s.extract::<f64>())
+            .map(|s| Number::from_f64(s).map_or(Value::Null, Value::Number))
+            .map_err(|_| conversion_error(value, "total_seconds() failed"));
+    }
+
+    Err(conversion_error(value, "unsupported type"))
+}
+
+fn dict_key(key: &PyAny) -> Result<String, TransformError> {
+    if let Ok(s) = key.downcast::<PyString>() {
+        return s
+            .to_str()
+            .map(str::to_owned)
+            .map_err(|_| conversion_error(key, "string is not valid UTF-8"));
+    }
+    if let Ok(b) = key.downcast::<PyBool>() {
+        return Ok(b.is_true().to_string());
+    }
+    if key.downcast::<PyLong>().is_ok() {
+        return key
+            .str()
+            .map(|s| s.to_string_lossy().into_owned())
+            .map_err(|_| conversion_error(key, "cannot stringify key"));
+    }
+
+    Err(conversion_error(key, "unsupported dict key type"))
+}
+
+fn has_method(value: &PyAny, name: &str) -> bool {
+    value.hasattr(name).unwrap_or(false)
+}
+
+fn conversion_error(value: &PyAny, reason: &str) -> TransformError {
+    let type_name = value.get_type().name().unwrap_or("object");
+
+    TransformError::Conversion(format!("{} ({})", reason, type_name))
+}
+
+#[cfg(all(test, feature = "cpython"))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn eval(code: &str) -> Result<Value, TransformError> {
+        Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            py.run("import datetime", Some(globals), None).unwrap();
+            from_py(py.eval(code, Some(globals), None).unwrap())
+        })
+    }
+
+    fn round_trip(value: &Value) -> Result<Value, TransformError> {
+        Python::with_gil(|py| from_py(to_py(py, value).as_ref(py)))
+    }
+
+    #[test]
+    fn integers_round_trip_at_the_64_bit_bounds() {
+        for value in [
+            json!(i64::MIN),
+            json!(i64::MAX),
+            json!(u64::MAX),
+            json!(0),
+            json!(-1),
+        ] {
+            assert_eq!(round_trip(&value), Ok(value));
+        }
+        assert_eq!(eval("2 ** 64 - 1"), Ok(json!(u64::MAX)));
+        assert_eq!(eval("-2 ** 63"), Ok(json!(i64::MIN)));
+    }
+
+    #[test]
+    fn integers_past_u64_are_conversion_errors() {
+        assert_eq!(
+            eval("2 ** 64"),
+            Err(TransformError::Conversion(
+                "integer out of 64-bit range (int)".into()
+            ))
+        );
+        assert_eq!(
+            eval("-2 ** 63 - 1"),
+            Err(TransformError::Conversion(
+                "integer out of 64-bit range (int)".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn floats_must_be_finite() {
+        assert_eq!(round_trip(&json!(1.5)), Ok(json!(1.5)));
+        assert_eq!(round_trip(&json!(1e300)), Ok(json!(1e300)));
+        for code in ["float('nan')", "float('inf')", "-float('inf')"] {
+            assert_eq!(
+                eval(code),
+                Err(TransformError::Conversion(
+                    "float is not finite (float)".into()
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn containers_round_trip() {
+        let value = json!({"a": [1, "two", null, true, {"b": 2.5}], "": {}});
+        assert_eq!(round_trip(&value), Ok(value));
+    }
+
+    #[test]
+    fn tuples_and_sets_become_arrays() {
+        assert_eq!(eval("(1, 'a', (None,))"), Ok(json!([1, "a", [null]])));
+        assert_eq!(eval("{3}"), Ok(json!([3])));
+        assert_eq!(eval("frozenset(['x'])"), Ok(json!(["x"])));
+    }
+
+    #[test]
+    fn dates_become_isoformat_and_timedeltas_seconds() {
+        assert_eq!(
+            eval("datetime.datetime(2018, 1, 1, 2, 2, 2, 123400)"),
+            Ok(json!("2018-01-01T02:02:02.123400"))
+        );
+        assert_eq!(eval("datetime.date(2018, 1, 1)"), Ok(json!("2018-01-01")));
+        assert_eq!(eval("datetime.time(2, 2)"), Ok(json!("02:02:00")));
+        assert_eq!(
+            eval("datetime.timedelta(minutes=1, milliseconds=500)"),
+            Ok(json!(60.5))
+        );
+    }
+
+    #[test]
+    fn int_and_bool_keys_are_stringified() {
+        assert_eq!(
+            eval("{1: 'a', -2: 'b', True: 'c', 'k': 'd'}"),
+            Ok(json!({"1": "c", "-2": "b", "k": "d"}))
+        );
+        assert_eq!(eval("{False: 0}"), Ok(json!({"false": 0})));
+        assert_eq!(
+            eval("{(1, 2): 0}"),
+            Err(TransformError::Conversion(
+                "unsupported dict key type (tuple)".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn other_types_are_conversion_errors() {
+        assert_eq!(
+            eval("object()"),
+            Err(TransformError::Conversion(
+                "unsupported type (object)".into()
+            ))
+        );
+        assert_eq!(
+            eval("b'x'"),
+            Err(TransformError::Conversion(
+                "unsupported type (bytes)".into()
+            ))
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Records that failed to transform, written aside as NDJSON so the rest
+//! of a batch can carry on.
+
+use std::io::{self, Write};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    error::{ErrorCategory, TransformError},
+    spec::ErrorPolicy,
+};
+
+/// One failed transformation, with everything needed to replay it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// The input record, unmodified.
+    pub record: Value,
+    /// Output field that failed; none for whole-record transforms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub params: Map<String, Value>,
+    /// How the failure was handled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<ErrorPolicy>,
+    pub category: ErrorCategory,
+    pub error: TransformError,
+    /// Where the record was read from, when it came from a stream.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+}
+
+impl DeadLetter {
+    pub fn new(record: Value, error: TransformError) -> Self {
+        DeadLetter {
+            record,
+            field: None,
+            method: None,
+            params: Map::new(),
+            policy: None,
+            category: error.category(),
+            error,
+            input: None,
+            line: None,
+        }
+    }
+
+    /// Record where the failed record was read from.
+    pub fn at(mut self, input: &str, line: usize) -> Self {
+        self.input = Some(input.to_owned());
+        self.line = Some(line);
+        self
+    }
+}
+
+/// Writes dead letters as newline-delimited JSON.
+pub struct DeadLetterSink<W: Write> {
+    writer: W,
+    written: usize,
+}
+
+impl<W: Write> DeadLetterSink<W> {
+    pub fn new(writer: W) -> Self {
+        DeadLetterSink { writer, written: 0 }
+    }
+
+    pub fn write(&mut self, letter: &DeadLetter) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, letter)?;
+        self.writer.write_all(b"\n")?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Number of dead letters written so far.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn dead_letters_are_one_json_object_per_line() {
+        let mut params = Map::new();
+        params.insert("tz_offset_in_minute".into(), json!(420));
+        let letter = DeadLetter {
+            field: Some("local_date".into()),
+            method: Some("hwlog_time_to_local_date_index".into()),
+            params,
+            policy: Some(ErrorPolicy::NullField),
+            ..DeadLetter::new(
+                json!({"hwlog": {"time": "x"}}),
+                TransformError::Python {
+                    type_name: "ValueError".into(),
+                    message: "could not convert string to float: 'x'".into(),
+                    traceback: "Traceback (most recent call last):\n".into(),
+                },
+            )
+            .at("records.ndjson", 3)
+        };
+
+        let mut sink = DeadLetterSink::new(Vec::new());
+        sink.write(&letter).unwrap();
+        sink.write(&DeadLetter::new(json!(1), TransformError::MissingOutput))
+            .unwrap();
+        assert_eq!(sink.written(), 2);
+
+        let text = String::from_utf8(sink.into_inner()).unwrap();
+        let lines: Vec<Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                json!({
+                    "record": {"hwlog": {"time": "x"}},
+                    "field": "local_date",
+                    "method": "hwlog_time_to_local_date_index",
+                    "params": {"tz_offset_in_minute": 420},
+                    "policy": "null-field",
+                    "category": "python",
+                    "error": {"python": {
+                        "type_name": "ValueError",
+                        "message": "could not convert string to float: 'x'",
+                        "traceback": "Traceback (most recent call last):\n",
+                    }},
+                    "input": "records.ndjson",
+                    "line": 3,
+                }),
+                json!({"record": 1, "category": "missing_output", "error": "missing_output"}),
+            ]
+        );
+        assert!(text.ends_with('\n'));
+    }
+}
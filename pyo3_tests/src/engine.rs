@@ -0,0 +1,542 @@
+use std::{sync::Mutex, time::Duration};
+
+use pyo3::{prelude::*, types::PyDict};
+use serde_json::{Map, Value};
+
+use crate::{
+    backend::{BackendKind, ScriptBackend, Snippet},
+    cache::{self, CodeCache},
+    capture::{self, log_output, Capture, Captured},
+    convert::{from_py, to_py},
+    error::TransformError,
+    limits::{self, Limits},
+    loader::{self, FieldTransformerSource},
+    native::{self, NativeFn},
+    sandbox::{Sandbox, SandboxPolicy},
+};
+
+/// Embedded interpreter state shared by every custom code invocation.
+///
+/// The FieldTransformer module is imported once when the engine is built;
+/// each call then executes the snippet in a fresh copy of the resulting
+/// namespace, so snippets cannot leak variables into one another.
+///
+/// Snippets are compiled once and kept in an LRU cache keyed by a hash of
+/// their source, so running the same snippet over many records only pays
+/// the compile cost on the first one.
+///
+/// Unless built `without_sandbox`, snippets run under the default
+/// `SandboxPolicy`.
+///
+/// An engine can be shared between threads; what a call prints is
+/// captured for that call alone.
+pub struct Engine {
+    namespace: Py<PyDict>,
+    code_cache: Mutex<CodeCache<PyObject>>,
+    limits: Limits,
+    sandbox: Option<Sandbox>,
+    capture: Capture,
+    native_transforms: bool,
+}
+
+pub struct EngineBuilder {
+    source: FieldTransformerSource,
+    cache_capacity: usize,
+    limits: Limits,
+    sandbox: Option<SandboxPolicy>,
+    output_limit: usize,
+    native_transforms: bool,
+}
+
+impl Default for EngineBuilder {
+    fn default() -> Self {
+        EngineBuilder {
+            source: FieldTransformerSource::default(),
+            cache_capacity: cache::DEFAULT_CAPACITY,
+            limits: Limits::default(),
+            sandbox: Some(SandboxPolicy::default()),
+            output_limit: capture::DEFAULT_OUTPUT_LIMIT,
+            native_transforms: true,
+        }
+    }
+}
+
+impl EngineBuilder {
+    pub fn source(mut self, source: FieldTransformerSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Maximum number of compiled snippets kept around; 0 disables caching.
+    pub fn cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache_capacity = capacity;
+        self
+    }
+
+    /// Wall-clock time allowed for each `transform*` call.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.limits.timeout = Some(timeout);
+        self
+    }
+
+    /// Number of executed Python source lines allowed for each `transform*`
+    /// call.
+    pub fn max_operations(mut self, max_operations: u64) -> Self {
+        self.limits.max_operations = Some(max_operations);
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn sandbox(mut self, policy: SandboxPolicy) -> Self {
+        self.sandbox = Some(policy);
+        self
+    }
+
+    /// Give custom code the full builtins and unrestricted imports, like
+    /// `FieldTransformer.transform_with_custom_code` does.
+    pub fn without_sandbox(mut self) -> Self {
+        self.sandbox = None;
+        self
+    }
+
+    /// Characters of stdout and of stderr kept per call; the rest is
+    /// dropped and the output marked truncated.
+    pub fn output_limit(mut self, limit: usize) -> Self {
+        self.output_limit = limit;
+        self
+    }
+
+    /// Answer `transform` calls for methods ported to `native` without
+    /// entering Python; on by default. Turn it off when the loaded module
+    /// changes what those methods do.
+    pub fn native_transforms(mut self, enabled: bool) -> Self {
+        self.native_transforms = enabled;
+        self
+    }
+
+    pub fn build(self) -> Result<Engine, TransformError> {
+        Python::with_gil(|py| {
+            let sandbox = match &self.sandbox {
+                Some(policy) => Some(Sandbox::new(py, policy)?),
+                None => None,
+            };
+            let namespace = load_namespace(py, &self.source, sandbox.as_ref())?;
+            let capture = Capture::new(py, self.output_limit)
+                .map_err(|err| TransformError::from_py(py, err))?;
+
+            Ok(Engine {
+                namespace: namespace.into(),
+                code_cache: Mutex::new(CodeCache::new(self.cache_capacity)),
+                limits: self.limits,
+                sandbox,
+                capture,
+                native_transforms: self.native_transforms,
+            })
+        })
+    }
+}
+
+impl Engine {
+    pub fn new() -> Result<Self, TransformError> {
+        Self::builder().build()
+    }
+
+    pub fn load(source: &FieldTransformerSource) -> Result<Self, TransformError> {
+        Self::builder().source(source.clone()).build()
+    }
+
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::default()
+    }
+
+    /// Number of compiled snippets currently cached.
+    pub fn cached_snippets(&self) -> usize {
+        self.code_cache.lock().unwrap().len()
+    }
+
+    fn native_method(&self, method_name: &str) -> Option<NativeFn> {
+        self.native_transforms
+            .then(|| native::lookup(method_name))
+            .flatten()
+    }
+
+    /// Call `FieldTransformer.transform(value, method_name, **params)`.
+    ///
+    /// Anything the method prints is forwarded to the `log` facade; use
+    /// `run_transform` to get it back instead.
+    pub fn transform(
+        &self,
+        value: &Value,
+        method_name: &str,
+        params: &Map<String, Value>,
+    ) -> Result<Value, TransformError> {
+        log_output(self.run_transform(value, method_name, params))
+    }
+
+    /// Like `transform`, returning the captured stdout/stderr alongside.
+    pub fn run_transform(
+        &self,
+        value: &Value,
+        method_name: &str,
+        params: &Map<String, Value>,
+    ) -> Captured<Result<Value, TransformError>> {
+        if let Some(native) = self.native_method(method_name) {
+            return Captured::silent(native(value, params));
+        }
+
+        Python::with_gil(|py| {
+            self.captured(py, || {
+                let field_transformer = self
+                    .namespace
+                    .as_ref(py)
+                    .get_item("field_transformer")
+                    .ok_or_else(|| {
+                        TransformError::Load("`field_transformer` is not defined".into())
+                    })?;
+                let kwargs = PyDict::new(py);
+                for (key, param) in params {
+                    kwargs
+                        .set_item(key, to_py(py, param))
+                        .map_err(|err| self.py_error(py, err))?;
+                }
+
+                limits::run_limited(py, self.limits, || {
+                    let output = field_transformer
+                        .call_method("transform", (to_py(py, value), method_name), Some(kwargs))
+                        .map_err(|err| self.py_error(py, err))?;
+
+                    from_py(output)
+                })
+            })
+        })
+    }
+
+    /// Run `custom_code` with `value` bound to the `value` variable and
+    /// return whatever the snippet assigned to `output`.
+    ///
+    /// Anything the snippet prints is forwarded to the `log` facade; use
+    /// `run_custom_code` to get it back instead.
+    pub fn transform_with_custom_code(
+        &self,
+        value: &Value,
+        custom_code: &str,
+    ) -> Result<Value, TransformError> {
+        log_output(self.run_custom_code(value, custom_code))
+    }
+
+    /// Like `transform_with_custom_code`, returning the captured
+    /// stdout/stderr alongside.
+    pub fn run_custom_code(
+        &self,
+        value: &Value,
+        custom_code: &str,
+    ) -> Captured<Result<Value, TransformError>> {
+        Python::with_gil(|py| {
+            self.captured(py, || {
+                let globals = self
+                    .namespace
+                    .as_ref(py)
+                    .copy()
+                    .map_err(|err| self.py_error(py, err))?;
+                globals
+                    .set_item("value", to_py(py, value))
+                    .map_err(|err| self.py_error(py, err))?;
+
+                let code = self.compile_code(py, custom_code)?;
+                let exec = py
+                    .import("builtins")
+                    .and_then(|builtins| builtins.getattr("exec"))
+                    .map_err(|err| self.py_error(py, err))?;
+
+                limits::run_limited(py, self.limits, || {
+                    exec.call1((code, globals))
+                        .map_err(|err| self.py_error(py, err))?;
+
+                    match globals.get_item("output") {
+                        Some(output) => from_py(output),
+                        None => Err(TransformError::MissingOutput),
+                    }
+                })
+            })
+        })
+    }
+
+    fn captured<F>(&self, py: Python, f: F) -> Captured<Result<Value, TransformError>>
+    where
+        F: FnOnce() -> Result<Value, TransformError>,
+    {
+        self.capture.run(py, f).unwrap_or_else(|err| Captured {
+            result: Err(TransformError::from_py(py, err)),
+            stdout: String::new(),
+            stderr: String::new(),
+            truncated: false,
+        })
+    }
+
+    // The cache lock is never held while Python runs: the sandbox check
+    // walks the AST in Python, the interpreter hands the GIL to other
+    // threads mid-walk, and one of them waiting on the lock with the GIL
+    // held would deadlock both. Rejected snippets are not cached.
+    fn compile_code(&self, py: Python, custom_code: &str) -> Result<PyObject, TransformError> {
+        if let Some(code) = self.code_cache.lock().unwrap().get(custom_code) {
+            return Ok(code);
+        }
+
+        let compile = || -> PyResult<PyObject> {
+            let compile = py.import("builtins")?.getattr("compile")?;
+            let file_name = format!("<custom_code {:016x}>", cache::key(custom_code));
+            if let Some(sandbox) = &self.sandbox {
+                sandbox.check(py, custom_code, &file_name)?;
+            }
+
+            Ok(compile.call1((custom_code, file_name, "exec"))?.into())
+        };
+        let code = compile().map_err(|err| self.py_error(py, err))?;
+        self.code_cache
+            .lock()
+            .unwrap()
+            .insert(custom_code, code.clone_ref(py));
+        Ok(code)
+    }
+
+    fn py_error(&self, py: Python, err: PyErr) -> TransformError {
+        match &self.sandbox {
+            Some(sandbox) => sandbox.error(py, err),
+            None => TransformError::from_py(py, err),
+        }
+    }
+}
+
+impl ScriptBackend for Engine {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Pyo3
+    }
+
+    fn load_module(&mut self, source: &FieldTransformerSource) -> Result<(), TransformError> {
+        Python::with_gil(|py| {
+            self.namespace = load_namespace(py, source, self.sandbox.as_ref())?.into();
+            Ok(())
+        })
+    }
+
+    fn compile(&self, custom_code: &str) -> Result<Snippet, TransformError> {
+        Python::with_gil(|py| self.compile_code(py, custom_code))?;
+        Ok(Snippet::new(custom_code))
+    }
+
+    fn call(&self, snippet: &Snippet, value: &Value) -> Result<Value, TransformError> {
+        Engine::transform_with_custom_code(self, value, snippet.source())
+    }
+
+    fn transform(
+        &self,
+        value: &Value,
+        method_name: &str,
+        params: &Map<String, Value>,
+    ) -> Result<Value, TransformError> {
+        Engine::transform(self, value, method_name, params)
+    }
+
+    fn transform_with_custom_code(
+        &self,
+        value: &Value,
+        custom_code: &str,
+    ) -> Result<Value, TransformError> {
+        Engine::transform_with_custom_code(self, value, custom_code)
+    }
+}
+
+fn load_namespace<'py>(
+    py: Python<'py>,
+    source: &FieldTransformerSource,
+    sandbox: Option<&Sandbox>,
+) -> Result<&'py PyDict, TransformError> {
+    let namespace = loader::load_namespace(py, source)?;
+    if let Some(sandbox) = sandbox {
+        sandbox.apply(py, namespace)?;
+    }
+
+    Ok(namespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// A FieldTransformer whose `transform` answers every method itself.
+    const STUB_MODULE: &str = r#"
+class FieldTransformer:
+    EXEC_SAFE_DICT = {}
+
+    def transform(self, value, method_name, **params):
+        return "python"
+"#;
+
+    fn stub_engine(native_transforms: bool) -> Engine {
+        Engine::builder()
+            .source(FieldTransformerSource::Code(STUB_MODULE.into()))
+            .native_transforms(native_transforms)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn custom_code_sees_value_and_returns_output() {
+        let engine = Engine::new().unwrap();
+        let captured = engine.run_custom_code(&json!({"a": [1, 2]}), "output = value['a'][1] * 10");
+        assert_eq!(captured.result, Ok(json!(20)));
+    }
+
+    #[test]
+    fn custom_code_reaches_field_transformer() {
+        let engine = Engine::new().unwrap();
+        let output = engine.transform_with_custom_code(
+            &json!("DW.MM.1-Flex.1.ZH"),
+            "output = field_transformer.extract_ate_id(value)[2]",
+        );
+        assert_eq!(output, Ok(json!("FLEX")));
+    }
+
+    #[test]
+    fn custom_code_without_output_is_an_error() {
+        let engine = Engine::new().unwrap();
+        let output = engine.transform_with_custom_code(&json!(1), "result = value");
+        assert_eq!(output, Err(TransformError::MissingOutput));
+    }
+
+    #[test]
+    fn snippets_do_not_share_variables() {
+        let engine = Engine::new().unwrap();
+        engine
+            .transform_with_custom_code(&json!(1), "leaked = value\noutput = value")
+            .unwrap();
+        let output = engine.transform_with_custom_code(
+            &json!(2),
+            "try:\n    output = leaked\nexcept NameError:\n    output = None",
+        );
+        assert_eq!(output, Ok(Value::Null));
+    }
+
+    #[test]
+    fn snippets_are_compiled_once() {
+        let engine = Engine::new().unwrap();
+        for value in 0..3 {
+            engine
+                .transform_with_custom_code(&json!(value), "output = value + 1")
+                .unwrap();
+        }
+        assert_eq!(engine.cached_snippets(), 1);
+
+        engine
+            .transform_with_custom_code(&json!(0), "output = value - 1")
+            .unwrap();
+        assert_eq!(engine.cached_snippets(), 2);
+    }
+
+    #[test]
+    fn zero_cache_capacity_keeps_nothing() {
+        let engine = Engine::builder().cache_capacity(0).build().unwrap();
+        let output = engine.transform_with_custom_code(&json!(1), "output = value + 1");
+        assert_eq!(output, Ok(json!(2)));
+        assert_eq!(engine.cached_snippets(), 0);
+    }
+
+    #[test]
+    fn native_transforms_answer_ported_methods() {
+        let value = json!({"$date": "2018-01-01T02:02:02.123Z"});
+        let output = stub_engine(true).transform(&value, "to_date_index", &Map::new());
+        assert_eq!(output, Ok(json!(20180101)));
+    }
+
+    #[test]
+    fn native_transforms_off_calls_the_module() {
+        let value = json!({"$date": "2018-01-01T02:02:02.123Z"});
+        let output = stub_engine(false).transform(&value, "to_date_index", &Map::new());
+        assert_eq!(output, Ok(json!("python")));
+    }
+
+    #[test]
+    fn unported_methods_call_the_module() {
+        let output = stub_engine(true).transform(&json!("1.0"), "normalize_version", &Map::new());
+        assert_eq!(output, Ok(json!("python")));
+    }
+
+    /// Run `snippet(thread)` on `threads` threads sharing `engine`, failing
+    /// rather than hanging if they deadlock.
+    fn run_concurrently(
+        engine: Engine,
+        threads: usize,
+        snippet: fn(usize) -> String,
+    ) -> Vec<Result<Value, TransformError>> {
+        let engine = std::sync::Arc::new(engine);
+        let (done, results) = std::sync::mpsc::channel();
+        for thread in 0..threads {
+            let (engine, done) = (engine.clone(), done.clone());
+            std::thread::spawn(move || {
+                let output = engine.transform_with_custom_code(&json!(thread), &snippet(thread));
+                done.send((thread, output)).unwrap();
+            });
+        }
+
+        let mut outputs: Vec<_> = (0..threads)
+            .map(|_| {
+                results
+                    .recv_timeout(Duration::from_secs(60))
+                    .expect("threads sharing an engine deadlocked")
+            })
+            .collect();
+        outputs.sort_by_key(|(thread, _)| *thread);
+        outputs.into_iter().map(|(_, output)| output).collect()
+    }
+
+    /// A snippet long enough that compiling it gives up the GIL.
+    fn long_snippet(thread: usize) -> String {
+        let mut code = String::new();
+        for line in 0..3000 {
+            code.push_str(&format!("x{} = value + {}\n", line, line));
+        }
+        code.push_str(&format!("output = value * 10 + {}\n", thread % 10));
+        code
+    }
+
+    #[test]
+    fn threads_compile_while_sharing_the_cache() {
+        let outputs = run_concurrently(Engine::new().unwrap(), 4, long_snippet);
+        let expected: Vec<_> = (0..4).map(|thread| Ok(json!(thread * 11))).collect();
+        assert_eq!(outputs, expected);
+    }
+
+    #[test]
+    fn threads_sandbox_check_while_sharing_the_cache() {
+        fn snippet(thread: usize) -> String {
+            let mut code = long_snippet(thread);
+            if thread % 2 == 1 {
+                code.push_str("kind = value.__class__\n");
+            }
+            code
+        }
+
+        let engine = Engine::new().unwrap();
+        let outputs = run_concurrently(engine, 4, snippet);
+        let denied = || {
+            Err(TransformError::Sandbox(
+                "access to attribute '__class__' is not allowed (line 3002)".into(),
+            ))
+        };
+        assert_eq!(outputs, [Ok(json!(0)), denied(), Ok(json!(22)), denied()]);
+    }
+
+    #[test]
+    fn rejected_snippets_are_not_cached() {
+        let engine = Engine::new().unwrap();
+        let output = engine.transform_with_custom_code(&json!(1), "output = value.__class__");
+        assert!(matches!(output, Err(TransformError::Sandbox(_))));
+        assert_eq!(engine.cached_snippets(), 0);
+    }
+}
@@ -0,0 +1,355 @@
+use std::fmt;
+
+#[cfg(feature = "cpython")]
+use pyo3::{exceptions::PySyntaxError, prelude::*, types::PyTuple};
+use serde::{Deserialize, Serialize};
+
+use crate::limits::Limit;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransformError {
+    /// The custom code does not parse.
+    Syntax {
+        message: String,
+        line: Option<usize>,
+        column: Option<usize>,
+    },
+    /// A `TransformationException` raised by FieldTransformer itself.
+    Transformation(String),
+    /// Any other Python exception.
+    Python {
+        type_name: String,
+        message: String,
+        traceback: String,
+    },
+    /// The FieldTransformer module could not be read.
+    Load(String),
+    /// The custom code finished without assigning `output`.
+    MissingOutput,
+    /// A record lacks a path that a record-scoped transform requires.
+    MissingInput(String),
+    /// A value could not be converted between Rust and Python.
+    Conversion(String),
+    /// The custom code did something the sandbox policy forbids.
+    Sandbox(String),
+    /// The call ran past its wall-clock limit or operation budget.
+    Timeout(Limit),
+    /// A pool worker process died or broke protocol.
+    Worker(String),
+}
+
+/// Coarse kind of a `TransformError`, for routing failed records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    Syntax,
+    Transformation,
+    Python,
+    Load,
+    MissingOutput,
+    MissingInput,
+    Conversion,
+    Sandbox,
+    Timeout,
+    Worker,
+}
+
+impl ErrorCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCategory::Syntax => "syntax",
+            ErrorCategory::Transformation => "transformation",
+            ErrorCategory::Python => "python",
+            ErrorCategory::Load => "load",
+            ErrorCategory::MissingOutput => "missing_output",
+            ErrorCategory::MissingInput => "missing_input",
+            ErrorCategory::Conversion => "conversion",
+            ErrorCategory::Sandbox => "sandbox",
+            ErrorCategory::Timeout => "timeout",
+            ErrorCategory::Worker => "worker",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl TransformError {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            TransformError::Syntax { .. } => ErrorCategory::Syntax,
+            TransformError::Transformation(_) => ErrorCategory::Transformation,
+            TransformError::Python { .. } => ErrorCategory::Python,
+            TransformError::Load(_) => ErrorCategory::Load,
+            TransformError::MissingOutput => ErrorCategory::MissingOutput,
+            TransformError::MissingInput(_) => ErrorCategory::MissingInput,
+            TransformError::Conversion(_) => ErrorCategory::Conversion,
+            TransformError::Sandbox(_) => ErrorCategory::Sandbox,
+            TransformError::Timeout(_) => ErrorCategory::Timeout,
+            TransformError::Worker(_) => ErrorCategory::Worker,
+        }
+    }
+
+    #[cfg(feature = "cpython")]
+    pub(crate) fn from_py(py: Python, err: PyErr) -> Self {
+        let value = err.pvalue(py);
+
+        if err.is_instance::<PySyntaxError>(py) {
+            let attr = |name: &str| value.getattr(name).ok().filter(|attr| !attr.is_none());
+            return TransformError::Syntax {
+                message: attr("msg")
+                    .and_then(|msg| msg.extract().ok())
+                    .unwrap_or_else(|| message(value)),
+                line: attr("lineno").and_then(|line| line.extract().ok()),
+                column: attr("offset").and_then(|column| column.extract().ok()),
+            };
+        }
+
+        let ptype = err.ptype(py);
+        if is_transformation_exception(ptype) {
+            return TransformError::Transformation(message(value));
+        }
+
+        TransformError::Python {
+            type_name: ptype.name().unwrap_or("Exception").to_owned(),
+            message: message(value),
+            traceback: traceback(py, &err),
+        }
+    }
+}
+
+/// `TransformationException` lives in a different module depending on which
+/// FieldTransformer variant is loaded, so match it by name anywhere in the
+/// exception's MRO.
+#[cfg(feature = "cpython")]
+fn is_transformation_exception(ptype: &PyAny) -> bool {
+    ptype
+        .getattr("__mro__")
+        .and_then(|mro| Ok(mro.downcast::<PyTuple>()?))
+        .map(|mro| {
+            mro.iter().any(|class| {
+                class
+                    .getattr("__name__")
+                    .and_then(|name| name.extract::<&str>())
+                    .is_ok_and(|name| name == "TransformationException")
+            })
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(feature = "cpython")]
+fn message(value: &PyAny) -> String {
+    value
+        .str()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(feature = "cpython")]
+fn traceback(py: Python, err: &PyErr) -> String {
+    let format = || -> PyResult<String> {
+        let lines = py.import("traceback")?.call_method1(
+            "format_exception",
+            (err.ptype(py), err.pvalue(py), err.ptraceback(py)),
+        )?;
+        Ok(lines.extract::<Vec<String>>()?.concat())
+    };
+
+    format().unwrap_or_default()
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransformError::Syntax {
+                message,
+                line,
+                column,
+            } => {
+                write!(f, "syntax error: {}", message)?;
+                if let Some(line) = line {
+                    write!(f, " (line {}", line)?;
+                    if let Some(column) = column {
+                        write!(f, ", column {}", column)?;
+                    }
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            TransformError::Transformation(message) => {
+                write!(f, "TransformationException: {}", message)
+            }
+            TransformError::Python {
+                type_name, message, ..
+            } => write!(f, "{}: {}", type_name, message),
+            TransformError::Load(message) => {
+                write!(f, "failed to load field transformer: {}", message)
+            }
+            TransformError::MissingOutput => write!(f, "custom code did not assign `output`"),
+            TransformError::MissingInput(path) => write!(f, "record has no '{}'", path),
+            TransformError::Conversion(message) => write!(f, "conversion error: {}", message),
+            TransformError::Sandbox(message) => write!(f, "sandbox violation: {}", message),
+            TransformError::Timeout(limit) => write!(f, "timeout: {}", limit),
+            TransformError::Worker(message) => write!(f, "worker failure: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[cfg(feature = "cpython")]
+    fn raised(code: &str) -> TransformError {
+        Python::with_gil(|py| {
+            let err = py.run(code, None, None).unwrap_err();
+            TransformError::from_py(py, err)
+        })
+    }
+
+    #[cfg(feature = "cpython")]
+    #[test]
+    fn syntax_errors_carry_line_and_column() {
+        let err = Python::with_gil(|py| {
+            let compile = py.import("builtins").unwrap().getattr("compile").unwrap();
+            let err = compile
+                .call1(("x = 1\ny = (", "<snippet>", "exec"))
+                .unwrap_err();
+            TransformError::from_py(py, err)
+        });
+        assert_eq!(
+            err,
+            TransformError::Syntax {
+                message: "'(' was never closed".into(),
+                line: Some(2),
+                column: Some(5),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "syntax error: '(' was never closed (line 2, column 5)"
+        );
+    }
+
+    #[cfg(feature = "cpython")]
+    #[test]
+    fn transformation_exceptions_match_by_name() {
+        let code = "
+class TransformationException(Exception):
+    pass
+
+raise TransformationException('bad value')
+";
+        assert_eq!(
+            raised(code),
+            TransformError::Transformation("bad value".into())
+        );
+
+        let subclass = "
+class TransformationException(Exception):
+    pass
+
+class DateError(TransformationException):
+    pass
+
+raise DateError('bad date')
+";
+        assert_eq!(
+            raised(subclass),
+            TransformError::Transformation("bad date".into())
+        );
+    }
+
+    #[cfg(feature = "cpython")]
+    #[test]
+    fn other_exceptions_carry_a_traceback() {
+        let code = "
+def parse(value):
+    return int(value)
+
+parse('x')
+";
+        match raised(code) {
+            TransformError::Python {
+                type_name,
+                message,
+                traceback,
+            } => {
+                assert_eq!(type_name, "ValueError");
+                assert_eq!(message, "invalid literal for int() with base 10: 'x'");
+                assert!(traceback.starts_with("Traceback (most recent call last):\n"));
+                assert!(traceback.contains("in parse\n"));
+                assert!(traceback
+                    .ends_with("ValueError: invalid literal for int() with base 10: 'x'\n"));
+            }
+            other => panic!("expected a Python error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors_serialize_by_category() {
+        let errors = [
+            (
+                TransformError::Syntax {
+                    message: "invalid syntax".into(),
+                    line: Some(1),
+                    column: None,
+                },
+                json!({"syntax": {"message": "invalid syntax", "line": 1, "column": null}}),
+            ),
+            (
+                TransformError::Transformation("bad".into()),
+                json!({"transformation": "bad"}),
+            ),
+            (
+                TransformError::Python {
+                    type_name: "KeyError".into(),
+                    message: "'a'".into(),
+                    traceback: String::new(),
+                },
+                json!({"python": {"type_name": "KeyError", "message": "'a'", "traceback": ""}}),
+            ),
+            (TransformError::Load("gone".into()), json!({"load": "gone"})),
+            (TransformError::MissingOutput, json!("missing_output")),
+            (
+                TransformError::MissingInput("a.b".into()),
+                json!({"missing_input": "a.b"}),
+            ),
+            (
+                TransformError::Conversion("x".into()),
+                json!({"conversion": "x"}),
+            ),
+            (
+                TransformError::Sandbox("no".into()),
+                json!({"sandbox": "no"}),
+            ),
+            (
+                TransformError::Timeout(Limit::Operations(10)),
+                json!({"timeout": {"operations": 10}}),
+            ),
+            (
+                TransformError::Worker("died".into()),
+                json!({"worker": "died"}),
+            ),
+        ];
+
+        for (err, expected) in errors {
+            let category = json!(err.category());
+            assert_eq!(category, json!(err.category().as_str()));
+            let serialized = serde_json::to_value(&err).unwrap();
+            assert_eq!(serialized, expected, "{}", category);
+            assert_eq!(
+                serde_json::from_value::<TransformError>(serialized).unwrap(),
+                err
+            );
+        }
+    }
+}
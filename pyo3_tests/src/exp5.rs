@@ -1,32 +1,45 @@
-use pyo3::{prelude::*, py_run, types::PyDict};
-
-fn transform_with_custom_code(custom_code: &str) {
-    let field_transformer_code: &str = r#"
-
-import hashlib
-print(hashlib.algorithms_available)
-print(hashlib.algorithms_guaranteed)
-class FieldTransformer:
-    def to_upper(self, value: str) -> str:
-        return value.upper()
-field_transformer = FieldTransformer()
-    "#; 
-
-    let run_code = format!("{}\n{}", field_transformer_code, custom_code);
-    Python::with_gil(|py| {
-        let locals = PyDict::new(py);
-        py.run(&run_code, None, Some(locals)).unwrap();
-        let output = locals.get_item("output").unwrap();
-        println!("{}", output)
-    });
-}
+use pyo3_tests::Engine;
+use serde_json::{json, Map};
 
 fn main() {
     let custom_code = r#"
 print(field_transformer)
-input = "Hello"
-print(input)
-output = field_transformer.to_upper(input)
+print(value)
+output = field_transformer.uppercase(value)
 "#;
-    transform_with_custom_code(custom_code);
-}
\ No newline at end of file
+    let engine = Engine::new().expect("failed to load field transformer");
+
+    let captured = engine.run_custom_code(&json!("Hello"), custom_code);
+    print!("captured stdout:\n{}", captured.stdout);
+    match captured.result {
+        Ok(output) => println!("{}", output),
+        Err(err) => eprintln!("transform failed: {}", err),
+    }
+
+    let custom_code = "output = field_transformer.extract_ate_id(value)[2]";
+    match engine.transform_with_custom_code(&json!("DW.MM.1-Flex.2.ZH"), custom_code) {
+        Ok(output) => println!("{}", output),
+        Err(err) => eprintln!("transform failed: {}", err),
+    }
+
+    match engine.transform_with_custom_code(&json!("Hello"), "output = value.nope()") {
+        Ok(output) => println!("{}", output),
+        Err(err) => eprintln!("transform failed: {}", err),
+    }
+
+    let no_params = Map::new();
+    for (value, method_name) in [
+        (
+            json!({"$date": "2019-12-22T16:28:17.123400Z"}),
+            "bson_date_to_datetime",
+        ),
+        (json!("DW.MM.1-Flex.2.ZH"), "extract_ate_id"),
+        (json!(18446744073709551615u64), "stringify"),
+        (json!({"b": [1, 2.5, null, true]}), "json_object_hash"),
+    ] {
+        match engine.transform(&value, method_name, &no_params) {
+            Ok(output) => println!("{}({}) = {}", method_name, value, output),
+            Err(err) => eprintln!("{} failed: {}", method_name, err),
+        }
+    }
+}
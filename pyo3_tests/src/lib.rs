@@ -0,0 +1,54 @@
+// With only `convert`, the interpreter plumbing is compiled but unused.
+#![cfg_attr(
+    not(any(feature = "cpython", feature = "rustpython")),
+    allow(dead_code, unused_variables)
+)]
+
+#[cfg(not(any(feature = "cpython", feature = "rustpython", feature = "convert")))]
+compile_error!(
+    "enable at least one backend feature: `cpython` or `rustpython` (or `convert` for the native ports alone)"
+);
+
+pub mod backend;
+mod cache;
+pub mod capture;
+#[cfg(feature = "convert")]
+pub mod convert;
+pub mod deadletter;
+#[cfg(feature = "cpython")]
+pub mod engine;
+pub mod error;
+pub mod limits;
+pub mod loader;
+pub mod lookup;
+pub mod native;
+pub mod outputs;
+#[cfg(feature = "cpython")]
+pub mod pool;
+pub mod record;
+#[cfg(feature = "rustpython")]
+pub mod rustpython;
+pub mod sandbox;
+pub mod spec;
+#[cfg(feature = "cpython")]
+pub mod worker;
+
+pub use backend::{BackendKind, ScriptBackend, Snippet};
+pub use capture::Captured;
+pub use deadletter::{DeadLetter, DeadLetterSink};
+#[cfg(feature = "cpython")]
+pub use engine::{Engine, EngineBuilder};
+pub use error::{ErrorCategory, TransformError};
+pub use limits::Limits;
+pub use loader::FieldTransformerSource;
+pub use lookup::{LookupTables, TableSpec};
+pub use outputs::MultiOutput;
+#[cfg(feature = "cpython")]
+pub use pool::WorkerPool;
+pub use record::RecordMethod;
+#[cfg(feature = "rustpython")]
+pub use rustpython::{RustPythonEngine, RustPythonEngineBuilder};
+pub use sandbox::SandboxPolicy;
+pub use spec::{Applied, ErrorPolicy, FieldSpec, Spec};
+#[cfg(feature = "cpython")]
+pub use worker::{Task, WorkerConfig};
@@ -0,0 +1,260 @@
+#[cfg(feature = "cpython")]
+use std::{cell::RefCell, os::raw::c_int, ptr, time::Instant};
+use std::{fmt, time::Duration};
+
+#[cfg(feature = "cpython")]
+use pyo3::{create_exception, exceptions::PyBaseException, ffi, prelude::*};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "cpython")]
+use crate::error::TransformError;
+
+// Derives from BaseException so `except Exception:` in a snippet does not
+// swallow it; the hook keeps raising on every later event anyway.
+#[cfg(feature = "cpython")]
+create_exception!(pyo3_tests, TransformTimeout, PyBaseException);
+
+/// Per-call execution limits for Python code.
+///
+/// Both limits are enforced by a trace hook that runs on every executed
+/// source line, so they cannot interrupt a single long-running C call such
+/// as `time.sleep` or a huge `sum(range(...))`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    /// Wall-clock time allowed for one invocation.
+    pub timeout: Option<Duration>,
+    /// Number of executed source lines allowed for one invocation.
+    pub max_operations: Option<u64>,
+}
+
+impl Limits {
+    pub fn is_unlimited(&self) -> bool {
+        self.timeout.is_none() && self.max_operations.is_none()
+    }
+}
+
+/// The limit that stopped an invocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Limit {
+    WallClock(Duration),
+    Operations(u64),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::WallClock(timeout) => write!(f, "wall-clock limit of {:?} exceeded", timeout),
+            Limit::Operations(budget) => write!(f, "operation budget of {} exceeded", budget),
+        }
+    }
+}
+
+#[cfg(feature = "cpython")]
+struct Budget {
+    limits: Limits,
+    deadline: Option<Instant>,
+    operations: u64,
+    exceeded: Option<Limit>,
+}
+
+#[cfg(feature = "cpython")]
+impl Budget {
+    fn tick(&mut self) -> Option<Limit> {
+        if self.exceeded.is_some() {
+            return self.exceeded;
+        }
+
+        self.operations += 1;
+        if let Some(max_operations) = self.limits.max_operations {
+            if self.operations > max_operations {
+                self.exceeded = Some(Limit::Operations(max_operations));
+            }
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.limits.timeout) {
+            if Instant::now() >= deadline {
+                self.exceeded = Some(Limit::WallClock(timeout));
+            }
+        }
+
+        self.exceeded
+    }
+}
+
+#[cfg(feature = "cpython")]
+thread_local! {
+    static BUDGET: RefCell<Option<Budget>> = const { RefCell::new(None) };
+}
+
+#[cfg(feature = "cpython")]
+extern "C" fn trace(
+    _obj: *mut ffi::PyObject,
+    _frame: *mut ffi::PyFrameObject,
+    what: c_int,
+    _arg: *mut ffi::PyObject,
+) -> c_int {
+    if what != ffi::PyTrace_LINE && what != ffi::PyTrace_CALL {
+        return 0;
+    }
+
+    let exceeded = BUDGET.with(|budget| budget.borrow_mut().as_mut().and_then(Budget::tick));
+    match exceeded {
+        Some(limit) => {
+            // SAFETY: trace functions are only ever called with the GIL held.
+            let py = unsafe { Python::assume_gil_acquired() };
+            TransformTimeout::new_err(limit.to_string()).restore(py);
+            -1
+        }
+        None => 0,
+    }
+}
+
+/// Run `f` with `limits` enforced on the Python code it executes.
+///
+/// Whatever `f` returns, a tripped limit is reported as
+/// `TransformError::Timeout`, since the snippet may have caught the
+/// interruption and raised something else. A trace function already set
+/// with `sys.settrace`, such as a debugger's or coverage's, is set again
+/// afterwards.
+#[cfg(feature = "cpython")]
+pub(crate) fn run_limited<T, F>(py: Python, limits: Limits, f: F) -> Result<T, TransformError>
+where
+    F: FnOnce() -> Result<T, TransformError>,
+{
+    if limits.is_unlimited() {
+        return f();
+    }
+
+    let sys = py
+        .import("sys")
+        .map_err(|err| TransformError::from_py(py, err))?;
+    let previous: PyObject = sys
+        .call_method0("gettrace")
+        .map_err(|err| TransformError::from_py(py, err))?
+        .into();
+
+    BUDGET.with(|budget| {
+        *budget.borrow_mut() = Some(Budget {
+            limits,
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+            operations: 0,
+            exceeded: None,
+        })
+    });
+    // SAFETY: the GIL is held, as witnessed by `py`.
+    unsafe { ffi::PyEval_SetTrace(Some(trace), ptr::null_mut()) };
+
+    let result = f();
+
+    unsafe { ffi::PyEval_SetTrace(None, ptr::null_mut()) };
+    let exceeded = BUDGET.with(|budget| budget.borrow_mut().take().and_then(|b| b.exceeded));
+    if !previous.is_none(py) {
+        sys.call_method1("settrace", (previous,))
+            .map_err(|err| TransformError::from_py(py, err))?;
+    }
+
+    match exceeded {
+        Some(limit) => Err(TransformError::Timeout(limit)),
+        None => result,
+    }
+}
+
+#[cfg(all(test, feature = "cpython"))]
+mod tests {
+    use super::*;
+
+    fn run(limits: Limits, code: &str) -> Result<(), TransformError> {
+        Python::with_gil(|py| {
+            run_limited(py, limits, || {
+                py.run(code, None, None)
+                    .map_err(|err| TransformError::from_py(py, err))
+            })
+        })
+    }
+
+    fn timeout(timeout: Duration) -> Limits {
+        Limits {
+            timeout: Some(timeout),
+            ..Limits::default()
+        }
+    }
+
+    fn max_operations(max_operations: u64) -> Limits {
+        Limits {
+            max_operations: Some(max_operations),
+            ..Limits::default()
+        }
+    }
+
+    #[test]
+    fn infinite_loop_hits_the_wall_clock_limit() {
+        let limit = Duration::from_millis(50);
+        assert_eq!(
+            run(timeout(limit), "while True:\n    pass"),
+            Err(TransformError::Timeout(Limit::WallClock(limit)))
+        );
+    }
+
+    #[test]
+    fn long_loop_hits_the_operation_budget() {
+        assert_eq!(
+            run(max_operations(1_000), "for i in range(10 ** 9):\n    pass"),
+            Err(TransformError::Timeout(Limit::Operations(1_000)))
+        );
+    }
+
+    #[test]
+    fn caught_interruption_is_still_a_timeout() {
+        let code = "
+while True:
+    try:
+        while True:
+            pass
+    except BaseException:
+        raise ValueError('swallowed')
+";
+        assert_eq!(
+            run(max_operations(100), code),
+            Err(TransformError::Timeout(Limit::Operations(100)))
+        );
+    }
+
+    #[test]
+    fn code_within_limits_runs() {
+        let limits = Limits {
+            timeout: Some(Duration::from_secs(10)),
+            max_operations: Some(1_000),
+        };
+        assert_eq!(run(limits, "total = sum(range(100))"), Ok(()));
+    }
+
+    #[test]
+    fn previous_trace_function_is_restored() {
+        let code = "
+import sys
+
+def tracer(frame, event, arg):
+    return tracer
+
+sys.settrace(tracer)
+";
+        Python::with_gil(|py| {
+            let globals = pyo3::types::PyDict::new(py);
+            py.run(code, Some(globals), None).unwrap();
+            let tracer = globals.get_item("tracer").unwrap();
+
+            let result = run_limited(py, max_operations(1_000), || {
+                py.run("x = 1", None, None)
+                    .map_err(|err| TransformError::from_py(py, err))
+            });
+            let restored = py.import("sys").unwrap().call_method0("gettrace").unwrap();
+            py.import("sys")
+                .unwrap()
+                .call_method1("settrace", (py.None(),))
+                .unwrap();
+
+            assert_eq!(result, Ok(()));
+            assert_eq!(restored, tracer);
+        });
+    }
+}
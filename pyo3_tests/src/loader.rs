@@ -0,0 +1,221 @@
+use std::{fs, path::PathBuf};
+
+#[cfg(feature = "cpython")]
+use pyo3::{
+    prelude::*,
+    types::{PyDict, PyModule},
+};
+
+use crate::error::TransformError;
+
+/// `test.py`, the self-contained test copy of `field_transformer.py`,
+/// compiled into the binary.
+const FIELD_TRANSFORMER_TEST_COPY: &str = include_str!("../../test.py");
+
+pub(crate) const MODULE_NAME: &str = "field_transformer";
+
+/// Where the FieldTransformer Python module comes from.
+#[derive(Debug, Clone, Default)]
+pub enum FieldTransformerSource {
+    /// `test.py`, embedded at build time. It is the test copy of
+    /// `field_transformer.py`, not the module the ETL runs: FirebaseUtils
+    /// and TransformationException are inlined, and the methods that need
+    /// `iso8601` are commented out: `iso_8601_to_date_index`,
+    /// `iso_8601_to_time_index` and `_rfc3339_datetime_to_db_datetime`,
+    /// which `convert_bson_rfc3339_datetime` calls. Use `Path` or `Code` to
+    /// load another copy.
+    #[default]
+    EmbeddedTestCopy,
+    /// A module file on disk. It must be importable on its own, i.e. without
+    /// relative imports.
+    Path(PathBuf),
+    /// Module source provided by the caller.
+    Code(String),
+}
+
+impl FieldTransformerSource {
+    /// Module source and the file name to compile it under.
+    pub(crate) fn read(&self) -> Result<(String, String), TransformError> {
+        match self {
+            FieldTransformerSource::EmbeddedTestCopy => Ok((
+                FIELD_TRANSFORMER_TEST_COPY.to_owned(),
+                format!("<embedded test copy of {}.py>", MODULE_NAME),
+            )),
+            FieldTransformerSource::Path(path) => {
+                let code = fs::read_to_string(path)
+                    .map_err(|err| TransformError::Load(format!("{}: {}", path.display(), err)))?;
+                Ok((code, path.display().to_string()))
+            }
+            FieldTransformerSource::Code(code) => Ok((code.clone(), format!("<{}>", MODULE_NAME))),
+        }
+    }
+}
+
+/// A failure to import the module from `file_name` or set up its namespace.
+pub(crate) fn load_error(file_name: &str, err: TransformError) -> TransformError {
+    TransformError::Load(format!("{}: {}", file_name, err))
+}
+
+/// Import the FieldTransformer module and build the namespace custom code
+/// runs in, mirroring `FieldTransformer.transform_with_custom_code`: the
+/// `EXEC_SAFE_DICT` entries plus a ready `field_transformer` instance.
+#[cfg(feature = "cpython")]
+pub(crate) fn load_namespace<'py>(
+    py: Python<'py>,
+    source: &FieldTransformerSource,
+) -> Result<&'py PyDict, TransformError> {
+    let (code, file_name) = source.read()?;
+    let module = PyModule::from_code(py, &code, &file_name, MODULE_NAME)
+        .map_err(|err| load_error(&file_name, TransformError::from_py(py, err)))?;
+
+    let build = || -> PyResult<&'py PyDict> {
+        let class = module.getattr("FieldTransformer")?;
+        let namespace = class
+            .getattr("EXEC_SAFE_DICT")?
+            .downcast::<PyDict>()?
+            .copy()?;
+        namespace.set_item("field_transformer", class.call0()?)?;
+        namespace.set_item("FieldTransformer", class)?;
+        if let Ok(exception) = module.getattr("TransformationException") {
+            namespace.set_item("TransformationException", exception)?;
+        }
+        Ok(namespace)
+    };
+
+    build().map_err(|err| load_error(&file_name, TransformError::from_py(py, err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// A module file in the temp dir, removed when dropped.
+    struct ModuleFile(PathBuf);
+
+    impl ModuleFile {
+        fn new(test: &str, code: &str) -> Self {
+            let path = env::temp_dir().join(format!(
+                "pyo3_tests-loader-{}-{}.py",
+                test,
+                std::process::id()
+            ));
+            fs::write(&path, code).unwrap();
+            ModuleFile(path)
+        }
+
+        fn source(&self) -> FieldTransformerSource {
+            FieldTransformerSource::Path(self.0.clone())
+        }
+    }
+
+    impl Drop for ModuleFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    const MODULE: &str = r#"
+class TransformationException(Exception):
+    pass
+
+
+class FieldTransformer:
+    EXEC_SAFE_DICT = {"len": len}
+
+    def transform(self, value, method_name, **params):
+        return value
+"#;
+
+    #[test]
+    fn path_sources_read_the_file() {
+        let file = ModuleFile::new("read", MODULE);
+        let (code, file_name) = file.source().read().unwrap();
+        assert_eq!(code, MODULE);
+        assert_eq!(file_name, file.0.display().to_string());
+    }
+
+    #[test]
+    fn missing_files_fail_to_load() {
+        let path = env::temp_dir().join("pyo3_tests-loader-missing.py");
+        match FieldTransformerSource::Path(path.clone()).read() {
+            Err(TransformError::Load(message)) => {
+                assert!(
+                    message.starts_with(&format!("{}: ", path.display())),
+                    "{}",
+                    message
+                )
+            }
+            other => panic!("expected a load error, got {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "cpython")]
+    #[test]
+    fn path_modules_load_into_the_namespace() {
+        let file = ModuleFile::new("namespace", MODULE);
+        Python::with_gil(|py| {
+            let namespace = load_namespace(py, &file.source()).unwrap();
+            let mut names: Vec<String> = namespace
+                .keys()
+                .iter()
+                .map(|key| key.extract().unwrap())
+                .collect();
+            names.sort();
+            assert_eq!(
+                names,
+                [
+                    "FieldTransformer",
+                    "TransformationException",
+                    "field_transformer",
+                    "len"
+                ]
+            );
+        });
+    }
+
+    #[cfg(feature = "cpython")]
+    #[test]
+    fn broken_modules_fail_to_load() {
+        for (test, code, error) in [
+            (
+                "syntax",
+                "class FieldTransformer(:\n",
+                "syntax error: invalid syntax (line 1",
+            ),
+            (
+                "raises",
+                "raise ImportError('no iso8601')\n",
+                "ImportError: no iso8601",
+            ),
+            ("missing", "x = 1\n", "AttributeError"),
+            (
+                "init",
+                concat!(
+                    "class FieldTransformer:\n",
+                    "    EXEC_SAFE_DICT = {}\n",
+                    "    def __init__(self, firebase):\n",
+                    "        pass\n",
+                ),
+                "TypeError",
+            ),
+        ] {
+            let file = ModuleFile::new(test, code);
+            let message = Python::with_gil(|py| match load_namespace(py, &file.source()) {
+                Err(TransformError::Load(message)) => message,
+                other => panic!(
+                    "{}: expected a load error, got {:?}",
+                    test,
+                    other.map(|_| ())
+                ),
+            });
+            assert!(
+                message.starts_with(&format!("{}: ", file.0.display())),
+                "{}",
+                message
+            );
+            assert!(message.contains(error), "{}: {}", test, message);
+        }
+    }
+}
@@ -0,0 +1,402 @@
+//! Key → value tables read from CSV or JSON files.
+//!
+//! A spec declares its tables under `tables` and a field maps its value
+//! through one with `lookup: NAME`, the way `crc32_to_app_name` maps
+//! through its dict: the key is `str(value)` and keys not in the table
+//! give the table's default. Tables are read when the spec is loaded and
+//! read again when their file changes, checked at most once every
+//! `RELOAD_CHECK_INTERVAL`; a file that no longer parses leaves the
+//! previous contents in use.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{error::TransformError, native::py_str};
+
+/// How long a table goes without looking at its file.
+pub const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where a table comes from.
+///
+/// ```yaml
+/// tables:
+///   app_names:
+///     path: crc32_app_names.csv
+///     key: crc32
+///     value: app_name
+///     default: unknown
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TableSpec {
+    /// A JSON object if the name ends in `.json`, otherwise CSV with a
+    /// header row. Relative to the spec file.
+    pub path: PathBuf,
+    /// CSV column of the keys; the first if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// CSV column of the values; the second if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Result for keys not in the table; null if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    /// Read the file again when it changes.
+    #[serde(default = "reload_by_default")]
+    pub reload: bool,
+}
+
+fn reload_by_default() -> bool {
+    true
+}
+
+/// The entries of a table file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Table(HashMap<String, Value>);
+
+impl Table {
+    /// A CSV table: a header row naming the columns, then one entry per
+    /// row. Values are strings.
+    pub fn from_csv(text: &str, key: Option<&str>, value: Option<&str>) -> Result<Table, String> {
+        let mut rows = parse_csv(text)?.into_iter();
+        let header = rows.next().ok_or("no header row")?;
+        let column = |name: Option<&str>, position: usize| match name {
+            Some(name) => header
+                .iter()
+                .position(|column| column == name)
+                .ok_or_else(|| format!("no column '{}'", name)),
+            None if position < header.len() => Ok(position),
+            None => Err(format!("expected at least {} columns", position + 1)),
+        };
+        let (key, value) = (column(key, 0)?, column(value, 1)?);
+
+        let mut entries = HashMap::new();
+        for (index, row) in rows.enumerate() {
+            if row.len() == 1 && row[0].is_empty() {
+                continue;
+            }
+            match (row.get(key), row.get(value)) {
+                (Some(k), Some(v)) => {
+                    entries.insert(k.clone(), Value::String(v.clone()));
+                }
+                _ => {
+                    return Err(format!(
+                        "record {}: expected {} columns",
+                        index + 1,
+                        header.len()
+                    ))
+                }
+            }
+        }
+        Ok(Table(entries))
+    }
+
+    /// A JSON table: one object, its values of any type.
+    pub fn from_json(text: &str) -> Result<Table, String> {
+        match serde_json::from_str(text).map_err(|err| err.to_string())? {
+            Value::Object(entries) => Ok(Table(entries.into_iter().collect())),
+            _ => Err("expected a JSON object".into()),
+        }
+    }
+
+    pub fn read(
+        path: &Path,
+        key: Option<&str>,
+        value: Option<&str>,
+    ) -> Result<Table, TransformError> {
+        let error = |err: String| TransformError::Load(format!("{}: {}", path.display(), err));
+        let text = fs::read_to_string(path).map_err(|err| error(err.to_string()))?;
+        if path.extension().is_some_and(|ext| ext == "json") {
+            Table::from_json(&text).map_err(error)
+        } else {
+            Table::from_csv(&text, key, value).map_err(error)
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0.get(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A table kept in step with its file.
+#[derive(Debug)]
+pub struct LookupTable {
+    spec: TableSpec,
+    path: PathBuf,
+    state: RwLock<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    table: Arc<Table>,
+    modified: Option<SystemTime>,
+    checked: Instant,
+}
+
+impl LookupTable {
+    /// Read the table of `spec`, resolving its path against `base`.
+    pub fn open(spec: &TableSpec, base: &Path) -> Result<LookupTable, TransformError> {
+        let path = base.join(&spec.path);
+        let modified = modified(&path);
+        let table = Table::read(&path, spec.key.as_deref(), spec.value.as_deref())?;
+        Ok(LookupTable {
+            spec: spec.clone(),
+            path,
+            state: RwLock::new(State {
+                table: Arc::new(table),
+                modified,
+                checked: Instant::now(),
+            }),
+        })
+    }
+
+    /// The value `value` maps to.
+    pub fn lookup(&self, value: &Value) -> Value {
+        self.table()
+            .get(&py_str(value))
+            .or(self.spec.default.as_ref())
+            .cloned()
+            .unwrap_or(Value::Null)
+    }
+
+    /// The current contents, read again first if the file has changed
+    /// and was last checked long enough ago.
+    pub fn table(&self) -> Arc<Table> {
+        {
+            let state = self.state.read().unwrap_or_else(|err| err.into_inner());
+            if !self.spec.reload || state.checked.elapsed() < RELOAD_CHECK_INTERVAL {
+                return Arc::clone(&state.table);
+            }
+        }
+        if let Err(err) = self.reload() {
+            log::warn!("keeping the previous lookup table: {}", err);
+        }
+        Arc::clone(
+            &self
+                .state
+                .read()
+                .unwrap_or_else(|err| err.into_inner())
+                .table,
+        )
+    }
+
+    /// Read the file again if it changed; true when it did.
+    pub fn reload(&self) -> Result<bool, TransformError> {
+        let modified = modified(&self.path);
+        {
+            let mut state = self.state.write().unwrap_or_else(|err| err.into_inner());
+            state.checked = Instant::now();
+            if modified.is_some() && modified == state.modified {
+                return Ok(false);
+            }
+        }
+
+        let table = Table::read(
+            &self.path,
+            self.spec.key.as_deref(),
+            self.spec.value.as_deref(),
+        )?;
+        let mut state = self.state.write().unwrap_or_else(|err| err.into_inner());
+        state.table = Arc::new(table);
+        state.modified = modified;
+        Ok(true)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// The tables of a spec, by name. Clones share the loaded tables.
+#[derive(Debug, Clone, Default)]
+pub struct LookupTables(HashMap<String, Arc<LookupTable>>);
+
+impl LookupTables {
+    pub fn open(
+        specs: &BTreeMap<String, TableSpec>,
+        base: &Path,
+    ) -> Result<LookupTables, TransformError> {
+        specs
+            .iter()
+            .map(|(name, spec)| Ok((name.clone(), Arc::new(LookupTable::open(spec, base)?))))
+            .collect::<Result<_, _>>()
+            .map(LookupTables)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LookupTable> {
+        self.0.get(name).map(Arc::as_ref)
+    }
+
+    /// Read every changed table file again, whatever their `reload`
+    /// setting; the names of those that changed.
+    pub fn reload(&self) -> Result<Vec<String>, TransformError> {
+        let mut reloaded = Vec::new();
+        for (name, table) in &self.0 {
+            if table.reload()? {
+                reloaded.push(name.clone());
+            }
+        }
+        Ok(reloaded)
+    }
+}
+
+/// RFC 4180 records: comma-separated, fields optionally double-quoted
+/// with `""` for a quote, CRLF or LF line ends.
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut quote_line = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => {
+                quoted = true;
+                quote_line = line;
+            }
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+                line += 1;
+            }
+            '\n' => {
+                field.push(c);
+                line += 1;
+            }
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(format!("line {}: unterminated quoted field", quote_line));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs::File};
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn csv(text: &str) -> Result<Table, String> {
+        Table::from_csv(text, None, None)
+    }
+
+    #[test]
+    fn quoted_fields_hold_quotes_commas_and_newlines() {
+        let table = csv("key,value\n\"a,b\",\"say \"\"hi\"\"\"\nc,\"two\nlines\"\n").unwrap();
+        assert_eq!(table.get("a,b"), Some(&json!("say \"hi\"")));
+        assert_eq!(table.get("c"), Some(&json!("two\nlines")));
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn crlf_and_bom_are_skipped() {
+        let table = csv("\u{feff}key,value\r\na,1\r\nb,\"2\r\n3\"\r\n").unwrap();
+        assert_eq!(table.get("a"), Some(&json!("1")));
+        assert_eq!(table.get("b"), Some(&json!("2\r\n3")));
+        assert_eq!(table.get("\u{feff}key"), None);
+    }
+
+    #[test]
+    fn columns_are_picked_by_name() {
+        let table = Table::from_csv("id,name,app\n1,x,one\n", Some("id"), Some("app")).unwrap();
+        assert_eq!(table.get("1"), Some(&json!("one")));
+        assert_eq!(
+            Table::from_csv("id,name\n", Some("crc32"), None),
+            Err("no column 'crc32'".into())
+        );
+    }
+
+    #[test]
+    fn short_rows_and_open_quotes_are_errors() {
+        assert_eq!(
+            csv("key,value,note\na,1,x\n\nb\n"),
+            Err("record 3: expected 3 columns".into())
+        );
+        assert_eq!(
+            csv("key,value\na,\"1\nb,2\n"),
+            Err("line 2: unterminated quoted field".into())
+        );
+        assert_eq!(csv(""), Err("no header row".into()));
+    }
+
+    fn write(path: &Path, text: &str, modified: SystemTime) {
+        fs::write(path, text).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[test]
+    fn broken_reload_keeps_the_previous_table() {
+        let dir = env::temp_dir().join(format!("pyo3_tests-lookup-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("apps.csv");
+        let then = SystemTime::now() - Duration::from_secs(60);
+        write(&path, "crc32,app\n1,one\n", then);
+
+        let spec = TableSpec {
+            path: "apps.csv".into(),
+            key: None,
+            value: None,
+            default: Some(json!("unknown")),
+            reload: true,
+        };
+        let table = LookupTable::open(&spec, &dir).unwrap();
+        assert_eq!(table.lookup(&json!(1)), json!("one"));
+        assert_eq!(table.lookup(&json!(2)), json!("unknown"));
+        assert_eq!(table.reload(), Ok(false));
+
+        write(&path, "crc32,app\n1,\"one\n", then + Duration::from_secs(1));
+        assert!(matches!(table.reload(), Err(TransformError::Load(_))));
+        assert_eq!(table.lookup(&json!(1)), json!("one"));
+
+        write(&path, "crc32,app\n1,uno\n", then + Duration::from_secs(2));
+        let reloaded = table.reload();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(reloaded, Ok(true));
+        assert_eq!(table.lookup(&json!(1)), json!("uno"));
+    }
+}
@@ -0,0 +1,169 @@
+//! ATE (automated test equipment) station ids, e.g. `DW.MM.1-Flex.2.ZH`.
+//!
+//! `AteId::parse` tries the `ATE_PATTERN_*` regexes of `extract_ate_id`
+//! in the same order and with the same fall-through. The patterns are
+//! translated so they match what Python's `re` does with `re.I`: `\w` is
+//! a letter, a number or `_`, and ASCII letter ranges also cover the four
+//! letters that case-fold into them. Station numbers that do not fit an
+//! `i64` fail to convert; coming out of Python, only those beyond `u64`
+//! do.
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use super::{check_params, py_error, py_type_name};
+use crate::error::TransformError;
+
+/// Python's `\w` for str patterns.
+const WORD: &str = r"[\p{L}\p{N}_]";
+/// `[a-zA-Z]` under `re.I`: `İ`, `ı`, `ſ` and the Kelvin sign fold into it.
+const ALPHA: &str = r"[a-zA-Z\x{130}\x{131}\x{17F}\x{212A}]";
+
+// The patterns as `FieldTransformer` writes them.
+const STANDARD_1: &str = r"([\w]+)[.]([a-zA-Z]+)[.]([\w]+)-([\w]*)[.]([0-9]+)[.]([a-zA-Z]+)";
+const STANDARD_2: &str = r"([\w]+)[.]([\w]+)[.]([a-zA-Z]*)([0-9]*)";
+const STANDARD_3: &str = r"([\w]+)[.]([a-zA-Z]+)[.]([\w]+)[.]([0-9]*)-([a-zA-Z]+)[.]([a-zA-Z]+)";
+const STATION_2_5_1: &str = r"([\w]+)[.][2][.][5][.]([a-zA-Z]*)([0-9]+)";
+const STATION_2_5_2: &str = r"([\w]+)[.][W][.][2][.][5]-([\w]+)[.]([0-9]+)[.]([\w]+)";
+
+struct Patterns {
+    standard_1: Regex,
+    standard_2: Regex,
+    standard_3: Regex,
+    station_2_5_1: Regex,
+    station_2_5_2: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        // `re.match` anchors at the start only.
+        let compile = |pattern: &str| {
+            let pattern = pattern.replace(r"[\w]", WORD).replace("[a-zA-Z]", ALPHA);
+            Regex::new(&format!("(?i)^(?:{})", pattern)).expect("ATE pattern is valid")
+        };
+        Patterns {
+            standard_1: compile(STANDARD_1),
+            standard_2: compile(STANDARD_2),
+            standard_3: compile(STANDARD_3),
+            station_2_5_1: compile(STATION_2_5_1),
+            station_2_5_2: compile(STATION_2_5_2),
+        }
+    })
+}
+
+/// What `extract_ate_id` makes of an ATE id; fields the matching pattern
+/// does not capture are None.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AteId {
+    pub category: Option<String>,
+    pub station: Option<String>,
+    /// Uppercase; Vietnamese factories are all `VN`.
+    pub factory: Option<String>,
+    /// -1 when there is none.
+    pub station_number: i64,
+    /// `HCMC` for Vietnamese factories.
+    pub city: Option<String>,
+}
+
+impl AteId {
+    pub fn parse(ate_id: &str) -> Result<AteId, TransformError> {
+        let patterns = patterns();
+        let group = |captures: &regex::Captures, i: usize| Some(captures[i].to_owned());
+
+        let mut category = None;
+        let mut station = None;
+        let mut factory = None;
+        let mut station_number = None;
+        let mut city = None;
+
+        let mut matched = false;
+        if let Some(c) = patterns.standard_1.captures(ate_id) {
+            matched = true;
+            (category, station, factory) = (group(&c, 1), group(&c, 3), group(&c, 4));
+            (station_number, city) = (group(&c, 5), group(&c, 6));
+        } else if let Some(c) = patterns.standard_3.captures(ate_id) {
+            matched = true;
+            (category, station, station_number) = (group(&c, 1), group(&c, 3), group(&c, 4));
+            (factory, city) = (group(&c, 5), group(&c, 6));
+        }
+
+        // Left set when neither 2.5 pattern matches, unless STANDARD_2 does.
+        if !matched && ate_id.contains("2.5") {
+            station = Some("2.5".to_owned());
+            if let Some(c) = patterns.station_2_5_1.captures(ate_id) {
+                matched = true;
+                (category, factory, station_number) = (group(&c, 1), group(&c, 2), group(&c, 3));
+            } else if let Some(c) = patterns.station_2_5_2.captures(ate_id) {
+                matched = true;
+                (category, factory, station_number) = (group(&c, 1), group(&c, 2), group(&c, 3));
+                city = group(&c, 4);
+            }
+        }
+
+        if !matched {
+            if let Some(c) = patterns.standard_2.captures(ate_id) {
+                (category, station) = (group(&c, 1), group(&c, 2));
+                (factory, station_number) = (group(&c, 3), group(&c, 4));
+            }
+        }
+
+        if let Some(name) = factory.as_mut().filter(|name| !name.is_empty()) {
+            *name = name.to_uppercase();
+            if name.contains("VN") || name.contains("VIETNAM") {
+                *name = "VN".to_owned();
+                city = Some("HCMC".to_owned());
+            }
+        }
+
+        Ok(AteId {
+            category,
+            station,
+            factory,
+            station_number: parse_station_number(station_number.as_deref())?,
+            city,
+        })
+    }
+
+    /// The `(category, station, factory, station_number, city)` tuple
+    /// `extract_ate_id` returns.
+    pub fn to_value(&self) -> Value {
+        let text = |s: &Option<String>| s.clone().map_or(Value::Null, Value::String);
+        Value::Array(vec![
+            text(&self.category),
+            text(&self.station),
+            text(&self.factory),
+            self.station_number.into(),
+            text(&self.city),
+        ])
+    }
+}
+
+/// `int(station_number or -1)` for the ASCII digits a pattern captured.
+fn parse_station_number(digits: Option<&str>) -> Result<i64, TransformError> {
+    match digits {
+        None | Some("") => Ok(-1),
+        Some(digits) => digits
+            .parse()
+            .map_err(|_| TransformError::Conversion("integer out of 64-bit range (int)".into())),
+    }
+}
+
+pub(crate) fn transform_extract_ate_id(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("extract_ate_id", params, &[])?;
+    match value {
+        Value::String(ate_id) => AteId::parse(ate_id).map(|ate_id| ate_id.to_value()),
+        _ => Err(py_error(
+            "TypeError",
+            format!(
+                "expected string or bytes-like object, got '{}'",
+                py_type_name(value)
+            ),
+        )),
+    }
+}
@@ -0,0 +1,372 @@
+//! Brand resolution for `normalize_brand_id`, `transform_db_name_to_brand`
+//! and `transform_project_name_to_brand`.
+//!
+//! The brands, their per-namespace overrides and the Firebase projects
+//! come from a `BrandRegistry` file rather than from code; see
+//! `data/brands.yaml`, which is compiled in and used until another
+//! registry is installed.
+
+use std::{
+    collections::HashMap,
+    env, fmt, fs,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::{check_params, py_contains, py_error, py_type_name};
+use crate::error::TransformError;
+
+const EMBEDDED_BRANDS: &str = include_str!("../../data/brands.yaml");
+
+/// Environment variable naming the deployment namespace.
+pub const NAMESPACE_VAR: &str = "NAMESPACE";
+
+static INSTALLED: RwLock<Option<Arc<BrandRegistry>>> = RwLock::new(None);
+
+/// Deployment stage of a Firebase project.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Prd,
+    Stg,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Prd => "prd",
+            Stage::Stg => "stg",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Project {
+    pub brand: String,
+    pub env: Stage,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BrandConfig {
+    brands: Vec<String>,
+    default_namespace: String,
+    /// Namespace → matched brand → brand to report instead.
+    #[serde(default)]
+    namespaces: HashMap<String, HashMap<String, String>>,
+    #[serde(default)]
+    projects: HashMap<String, Project>,
+}
+
+/// Brands to recognise in names, and Firebase projects to map to them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrandRegistry {
+    /// Longest first; brands of equal length in file order.
+    brands: Vec<String>,
+    default_namespace: String,
+    namespaces: HashMap<String, HashMap<String, String>>,
+    projects: HashMap<String, Project>,
+}
+
+impl BrandRegistry {
+    /// The registry compiled into the binary.
+    pub fn embedded() -> BrandRegistry {
+        BrandRegistry::from_yaml(EMBEDDED_BRANDS).expect("embedded brand registry is valid")
+    }
+
+    /// Read a registry file: JSON if it ends in `.json`, YAML otherwise.
+    pub fn load(path: &Path) -> Result<BrandRegistry, TransformError> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| TransformError::Load(format!("{}: {}", path.display(), err)))?;
+        let registry = if path.extension().is_some_and(|ext| ext == "json") {
+            BrandRegistry::from_json(&text)
+        } else {
+            BrandRegistry::from_yaml(&text)
+        };
+
+        registry.map_err(|err| match err {
+            TransformError::Load(message) => {
+                TransformError::Load(format!("{}: {}", path.display(), message))
+            }
+            err => err,
+        })
+    }
+
+    pub fn from_yaml(text: &str) -> Result<BrandRegistry, TransformError> {
+        serde_yaml::from_str(text)
+            .map_err(|err| TransformError::Load(err.to_string()))
+            .and_then(BrandRegistry::new)
+    }
+
+    pub fn from_json(text: &str) -> Result<BrandRegistry, TransformError> {
+        serde_json::from_str(text)
+            .map_err(|err| TransformError::Load(err.to_string()))
+            .and_then(BrandRegistry::new)
+    }
+
+    fn new(config: BrandConfig) -> Result<BrandRegistry, TransformError> {
+        let BrandConfig {
+            mut brands,
+            default_namespace,
+            namespaces,
+            projects,
+        } = config;
+
+        if brands.iter().any(String::is_empty) {
+            return Err(TransformError::Load("brand names must not be empty".into()));
+        }
+        for (namespace, overrides) in &namespaces {
+            if let Some(brand) = overrides.keys().find(|brand| !brands.contains(brand)) {
+                return Err(TransformError::Load(format!(
+                    "namespace '{}' overrides unknown brand '{}'",
+                    namespace, brand
+                )));
+            }
+        }
+        // Stable, so equal lengths keep their order.
+        brands.sort_by_key(|brand| std::cmp::Reverse(brand.chars().count()));
+
+        Ok(BrandRegistry {
+            brands,
+            default_namespace: default_namespace.to_lowercase(),
+            namespaces,
+            projects,
+        })
+    }
+
+    /// The registry the native brand transforms use.
+    pub fn installed() -> Arc<BrandRegistry> {
+        let installed = INSTALLED.read().unwrap_or_else(|err| err.into_inner());
+        if let Some(registry) = installed.as_ref() {
+            return Arc::clone(registry);
+        }
+        drop(installed);
+
+        let mut installed = INSTALLED.write().unwrap_or_else(|err| err.into_inner());
+        Arc::clone(installed.get_or_insert_with(|| Arc::new(BrandRegistry::embedded())))
+    }
+
+    /// Make this the registry of the native brand transforms, in every
+    /// engine of the process.
+    pub fn install(self) {
+        *INSTALLED.write().unwrap_or_else(|err| err.into_inner()) = Some(Arc::new(self));
+    }
+
+    /// `$NAMESPACE` lowercased, or the registry's default namespace.
+    pub fn current_namespace(&self) -> String {
+        env::var(NAMESPACE_VAR)
+            .map(|namespace| namespace.to_lowercase())
+            .unwrap_or_else(|_| self.default_namespace.clone())
+    }
+
+    /// The brand in `name` under `namespace`: the longest brand `name`
+    /// contains, as overridden for the namespace.
+    pub fn brand_of(&self, name: &str, namespace: &str) -> Option<&str> {
+        self.find(namespace, |brand| Ok(name.contains(brand)))
+            .unwrap_or_default()
+    }
+
+    /// The first brand `contains` accepts, longest first, overridden for
+    /// `namespace`.
+    fn find(
+        &self,
+        namespace: &str,
+        mut contains: impl FnMut(&str) -> Result<bool, TransformError>,
+    ) -> Result<Option<&str>, TransformError> {
+        for brand in &self.brands {
+            if contains(brand)? {
+                let replacement = self
+                    .namespaces
+                    .get(namespace)
+                    .and_then(|overrides| overrides.get(brand));
+                return Ok(Some(replacement.unwrap_or(brand)));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn project(&self, name: &str) -> Option<&Project> {
+        self.projects.get(name)
+    }
+
+    /// The brand of the Firebase project `name`.
+    pub fn project_brand(&self, name: &str) -> Option<&str> {
+        self.project(name).map(|project| project.brand.as_str())
+    }
+
+    /// The deployment stage of the Firebase project `name`.
+    pub fn project_stage(&self, name: &str) -> Option<Stage> {
+        self.project(name).map(|project| project.env)
+    }
+}
+
+/// `normalize_brand_id` and `transform_db_name_to_brand`: the brand a
+/// value names, or the value itself when it names none.
+fn transform_brand(
+    method_name: &str,
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params(method_name, params, &[])?;
+    let registry = BrandRegistry::installed();
+    brand_in(&registry, &registry.current_namespace(), value)
+}
+
+/// The brand `value` names under `namespace` in `registry`, or `value`.
+fn brand_in(
+    registry: &BrandRegistry,
+    namespace: &str,
+    value: &Value,
+) -> Result<Value, TransformError> {
+    // `brand in value`
+    match registry.find(namespace, |brand| py_contains(value, brand))? {
+        Some(brand) => Ok(Value::String(brand.to_owned())),
+        None => Ok(value.clone()),
+    }
+}
+
+pub(crate) fn transform_normalize_brand_id(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    transform_brand("normalize_brand_id", value, params)
+}
+
+pub(crate) fn transform_db_name_to_brand(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    transform_brand("transform_db_name_to_brand", value, params)
+}
+
+pub(crate) fn transform_project_name_to_brand(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("transform_project_name_to_brand", params, &[])?;
+    project_brand_in(&BrandRegistry::installed(), value)
+}
+
+/// `transform_project_name_to_brand` against `registry`.
+fn project_brand_in(registry: &BrandRegistry, value: &Value) -> Result<Value, TransformError> {
+    match value {
+        Value::String(name) => Ok(registry
+            .project_brand(name)
+            .map_or(Value::Null, |brand| Value::String(brand.to_owned()))),
+        Value::Array(_) | Value::Object(_) => Err(py_error(
+            "TypeError",
+            format!("unhashable type: '{}'", py_type_name(value)),
+        )),
+        _ => Ok(Value::Null),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn registry(default_namespace: &str) -> BrandRegistry {
+        BrandRegistry::from_yaml(&format!(
+            "
+brands: [ab, abc, xy, zw, ab_long]
+default_namespace: {}
+namespaces:
+  {0}: {{ abc: abc_override }}
+  other: {{}}
+projects:
+  com.example.abc: {{ brand: abc, env: stg }}
+",
+            default_namespace
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn longest_brand_wins_and_ties_keep_file_order() {
+        let registry = registry("home");
+        assert_eq!(registry.brand_of("ab_long_abc", "other"), Some("ab_long"));
+        assert_eq!(registry.brand_of("x_abc_y", "other"), Some("abc"));
+        assert_eq!(registry.brand_of("zw_xy", "other"), Some("xy"));
+        assert_eq!(registry.brand_of("none", "other"), None);
+    }
+
+    #[test]
+    fn namespaces_override_the_matched_brand() {
+        let registry = registry("home");
+        assert_eq!(registry.brand_of("abc_prd", "home"), Some("abc_override"));
+        assert_eq!(registry.brand_of("abc_prd", "other"), Some("abc"));
+        assert_eq!(registry.brand_of("ab_prd", "home"), Some("ab"));
+    }
+
+    #[test]
+    fn projects_have_a_brand_and_a_stage() {
+        let registry = registry("home");
+        assert_eq!(registry.project_brand("com.example.abc"), Some("abc"));
+        assert_eq!(registry.project_stage("com.example.abc"), Some(Stage::Stg));
+        assert_eq!(registry.project_stage("com.example.none"), None);
+
+        let embedded = BrandRegistry::embedded();
+        assert_eq!(embedded.project_stage("com.fossil.q"), Some(Stage::Prd));
+        assert_eq!(
+            embedded.project_stage("com.misfit.fossilq.staging"),
+            Some(Stage::Stg)
+        );
+        assert_eq!(Stage::Prd.to_string(), "prd");
+    }
+
+    #[test]
+    fn projects_need_a_known_stage() {
+        let yaml = "brands: [ab]\ndefault_namespace: x\nprojects: { p: { brand: ab, env: dev } }";
+        assert!(matches!(
+            BrandRegistry::from_yaml(yaml),
+            Err(TransformError::Load(message)) if message.contains("unknown variant `dev`")
+        ));
+    }
+
+    #[test]
+    fn overrides_of_unknown_brands_are_rejected() {
+        let yaml = "brands: [ab]\ndefault_namespace: x\nnamespaces: { x: { cd: ab } }";
+        assert_eq!(
+            BrandRegistry::from_yaml(yaml),
+            Err(TransformError::Load(
+                "namespace 'x' overrides unknown brand 'cd'".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn transforms_follow_the_registry() {
+        let registry = registry("home");
+        let results = [
+            brand_in(&registry, "home", &json!("abc_stg")),
+            brand_in(&registry, "other", &json!("abc_stg")),
+            brand_in(&registry, "home", &json!("ab_long_x")),
+            brand_in(&registry, "home", &json!("fossil_prd")),
+            brand_in(&registry, "home", &json!(["ab", "x"])),
+            project_brand_in(&registry, &json!("com.example.abc")),
+            project_brand_in(&registry, &json!("com.fossil.q")),
+        ];
+        assert_eq!(
+            results,
+            [
+                Ok(json!("abc_override")),
+                Ok(json!("abc")),
+                Ok(json!("ab_long")),
+                Ok(json!("fossil_prd")),
+                Ok(json!("ab")),
+                Ok(json!("abc")),
+                Ok(Value::Null),
+            ]
+        );
+        assert_eq!(
+            project_brand_in(&BrandRegistry::embedded(), &json!("com.fossil.q")),
+            Ok(json!("fossil"))
+        );
+    }
+}
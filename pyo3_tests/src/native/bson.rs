@@ -0,0 +1,383 @@
+//! BSON-family transforms over `extjson`.
+//!
+//! Each port follows the branching of its Python method, including the
+//! errors it raises on shapes it does not handle, but hands the `$date`,
+//! `$oid` and `$numberLong` payloads to the Extended JSON decoder instead
+//! of re-parsing them, and never modifies its input. Beyond what Python
+//! accepts:
+//!
+//! * a canonical `{"$date": {"$numberLong": ...}}` is understood wherever
+//!   an integer `$date` is;
+//! * `$date` strings may carry a UTC offset; the date and time fields are
+//!   taken as written, like the attributes of an aware `datetime`;
+//! * `$date` strings may also separate date and time with a space, or be
+//!   a date alone;
+//! * ObjectIds come back as lowercase hex.
+//!
+//! `convert_bson_rfc3339_datetime` follows `field_transformer.py`, which
+//! parses strings with `iso8601`, and reads integer timestamps as UTC where
+//! Python uses the host's local time zone.
+
+use chrono::NaiveDateTime;
+use serde_json::{Map, Value};
+
+use super::{
+    check_params,
+    datetime::{
+        date_string_to_datetime, datetime_to_date_index, datetime_to_db_datetime,
+        datetime_to_time_index, isoformat, normalize_second_timestamp, strptime, subscript,
+        utc_from_timestamp, MAX_SECOND_TIMESTAMP,
+    },
+    extjson::{parse_date_string, BsonDate, ObjectId},
+    py_contains, py_error, py_float, py_int_str, py_repr_str, py_str, py_type_name, truthy,
+};
+use crate::error::TransformError;
+
+const ISO_DATETIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S.%f";
+
+pub(crate) fn transform_convert_bson_object_id(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("convert_bson_object_id", params, &[])?;
+    if value.is_string() {
+        return Ok(value.clone());
+    }
+    if !py_contains(value, "$oid")? {
+        return Ok(Value::Null);
+    }
+
+    Ok(Value::String(match subscript(value, "$oid")? {
+        Value::String(oid) => ObjectId::parse_str(oid)
+            .map(|oid| oid.to_hex())
+            .unwrap_or_else(|_| oid.clone()),
+        oid => py_str(oid),
+    }))
+}
+
+/// A timestamp as Python holds it: `int` until it is divided.
+#[derive(Clone, Copy)]
+enum Timestamp {
+    Int(i128),
+    Float(f64),
+}
+
+pub(crate) fn transform_convert_bson_long_datetime(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("convert_bson_long_datetime", params, &[])?;
+    let timestamp = match value {
+        Value::String(s) => Some(Timestamp::Int(py_int_str(s)?)),
+        Value::Bool(_) | Value::Number(_) => int_value(value).map(Timestamp::Int),
+        Value::Object(map) => {
+            let mut timestamp = None;
+            if let Some(date) = map.get("$date") {
+                if let Some(int) = int_value(date) {
+                    timestamp = Some(Timestamp::Int(int));
+                } else if let Value::String(s) = date {
+                    timestamp = rfc3339_to_timestamp(s).map(Timestamp::Float);
+                } else if py_contains(date, "$numberLong")? {
+                    timestamp = Some(Timestamp::Int(number_long(date)?));
+                }
+            }
+            if let Some(Value::String(s)) = map.get("$numberLong") {
+                if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+                    timestamp = Some(Timestamp::Int(py_int_str(s)?));
+                }
+            }
+            timestamp
+        }
+        _ => None,
+    };
+
+    let seconds = match timestamp {
+        Some(Timestamp::Int(int)) if int != 0 => {
+            if int > MAX_SECOND_TIMESTAMP as i128 {
+                normalize_second_timestamp(int as f64 / 1000.0).trunc()
+            } else {
+                int as f64
+            }
+        }
+        Some(Timestamp::Float(float)) if float != 0.0 => normalize_second_timestamp(float).trunc(),
+        _ => return Ok(Value::Null),
+    };
+
+    let datetime = utc_from_timestamp(seconds)?;
+    Ok(Value::String(datetime_to_db_datetime(&datetime, false)))
+}
+
+pub(crate) fn transform_convert_bson_rfc3339_datetime(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params(
+        "convert_bson_rfc3339_datetime",
+        params,
+        &["keep_zero_microsecond"],
+    )?;
+    let keep_zero_microsecond = params.get("keep_zero_microsecond").is_none_or(truthy);
+
+    let date = match value {
+        Value::String(_) => value,
+        _ if py_contains(value, "$date")? => subscript(value, "$date")?,
+        _ => return Ok(Value::Null),
+    };
+    if !truthy(date) {
+        return Ok(Value::Null);
+    }
+
+    let not_a_string = || {
+        py_error(
+            "ParseError",
+            format!(
+                "expected string or bytes-like object, got '{}'",
+                py_type_name(date)
+            ),
+        )
+    };
+    let datetime = match date {
+        Value::String(s) => parse_date_string(s)
+            .map_err(|_| {
+                py_error(
+                    "ParseError",
+                    format!("Unable to parse date string {}", py_repr_str(s)),
+                )
+            })?
+            .naive_local(),
+        Value::Object(_) => match BsonDate::from_ext(date) {
+            Ok(BsonDate::Millis(millis)) => {
+                utc_from_timestamp(normalize_second_timestamp(millis as f64))?
+            }
+            _ => return Err(not_a_string()),
+        },
+        _ if int_value(date).is_some() => {
+            utc_from_timestamp(normalize_second_timestamp(py_float(date)?))?
+        }
+        _ => return Err(not_a_string()),
+    };
+
+    Ok(Value::String(datetime_to_db_datetime(
+        &datetime,
+        keep_zero_microsecond,
+    )))
+}
+
+pub(crate) fn transform_to_date_index(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("to_date_index", params, &[])?;
+    let datetime = bson_date(value)?;
+    Ok(datetime
+        .map_or(0, |datetime| datetime_to_date_index(&datetime))
+        .into())
+}
+
+pub(crate) fn transform_bson_date_to_datetime(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("bson_date_to_datetime", params, &[])?;
+    let datetime = bson_date(value)?;
+    Ok(datetime.map_or(Value::Null, |datetime| Value::String(isoformat(&datetime))))
+}
+
+pub(crate) fn transform_bson_date_to_time_index(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("bson_date_to_time_index", params, &[])?;
+    let datetime = match value {
+        Value::String(s) => Some(date_string_to_datetime(s)?),
+        _ if py_contains(value, "$date")? => date_payload(subscript(value, "$date")?)?,
+        _ => None,
+    };
+
+    Ok(datetime.map_or(Value::Null, |datetime| {
+        datetime_to_time_index(&datetime).into()
+    }))
+}
+
+/// The date of a `to_date_index` / `bson_date_to_datetime` argument: an
+/// ISO 8601 string with microseconds, or a `$date` object.
+fn bson_date(value: &Value) -> Result<Option<NaiveDateTime>, TransformError> {
+    if !truthy(value) {
+        return Ok(None);
+    }
+
+    match value {
+        Value::String(s) => {
+            let s = s.strip_suffix('Z').unwrap_or(s);
+            strptime(s, ISO_DATETIME_FORMAT).map(Some)
+        }
+        _ if py_contains(value, "$date")? => date_payload(subscript(value, "$date")?),
+        _ => Ok(None),
+    }
+}
+
+/// Wall-clock time of a `$date` payload; None for shapes Python ignores.
+fn date_payload(date: &Value) -> Result<Option<NaiveDateTime>, TransformError> {
+    match date {
+        Value::String(s) => match parse_date_string(s) {
+            Ok(datetime) => Ok(Some(datetime.naive_local())),
+            // Report the failure the way Python's strptime does.
+            Err(_) => date_string_to_datetime(s.strip_suffix('Z').unwrap_or(s)).map(Some),
+        },
+        Value::Object(_) => match BsonDate::from_ext(date) {
+            Ok(date) => date.wall_time().map(Some),
+            Err(_) => Ok(None),
+        },
+        _ => match int_value(date) {
+            Some(millis) => utc_from_timestamp(millis as f64 / 1000.0).map(Some),
+            None => Ok(None),
+        },
+    }
+}
+
+/// `(parsed - epoch).total_seconds()` of a `$date` string, None when it
+/// does not parse.
+fn rfc3339_to_timestamp(value: &str) -> Option<f64> {
+    parse_date_string(value)
+        .ok()
+        .map(|datetime| BsonDate::Iso(datetime).timestamp())
+}
+
+/// `int(date["$numberLong"])`.
+fn number_long(date: &Value) -> Result<i128, TransformError> {
+    let number = subscript(date, "$numberLong")?;
+    if let Some(int) = int_value(number) {
+        return Ok(int);
+    }
+
+    match number {
+        Value::String(s) => py_int_str(s),
+        Value::Number(_) => Ok(py_float(number)?.trunc() as i128),
+        _ => Err(py_error(
+            "TypeError",
+            format!(
+                "int() argument must be a string, a bytes-like object or a real number, not '{}'",
+                py_type_name(number)
+            ),
+        )),
+    }
+}
+
+/// The value of a Python `int` (including `bool`), None for anything else.
+fn int_value(value: &Value) -> Option<i128> {
+    match value {
+        Value::Bool(b) => Some(i128::from(*b)),
+        Value::Number(n) => n
+            .as_i64()
+            .map(i128::from)
+            .or_else(|| n.as_u64().map(i128::from)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    type Transform = fn(&Value, &Map<String, Value>) -> Result<Value, TransformError>;
+
+    fn call(transform: Transform, value: Value) -> Result<Value, TransformError> {
+        transform(&value, &Map::new())
+    }
+
+    fn type_name(result: Result<Value, TransformError>) -> String {
+        match result {
+            Err(TransformError::Python { type_name, .. }) => type_name,
+            other => panic!("expected a Python error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn object_ids_come_back_as_lowercase_hex() {
+        let oid = transform_convert_bson_object_id;
+        assert_eq!(
+            call(oid, json!({"$oid": "5A4A9B1A2F4C3B0001A1B2C3"})),
+            Ok(json!("5a4a9b1a2f4c3b0001a1b2c3"))
+        );
+        assert_eq!(call(oid, json!({"$oid": "short"})), Ok(json!("short")));
+        assert_eq!(call(oid, json!({"$oid": 12})), Ok(json!("12")));
+        assert_eq!(call(oid, json!("as is")), Ok(json!("as is")));
+        assert_eq!(call(oid, json!({"id": 1})), Ok(Value::Null));
+        assert_eq!(type_name(call(oid, json!(1))), "TypeError");
+    }
+
+    #[test]
+    fn every_date_shape_gives_the_same_index() {
+        for date in [
+            json!({"$date": 1514772122123i64}),
+            json!({"$date": {"$numberLong": "1514772122123"}}),
+            json!({"$date": "2018-01-01T02:02:02.123Z"}),
+            json!({"$date": "2018-01-01 02:02:02"}),
+            json!({"$date": "2018-01-01T02:02:02+07:00"}),
+            json!("2018-01-01T02:02:02.123000Z"),
+        ] {
+            assert_eq!(
+                call(transform_to_date_index, date.clone()),
+                Ok(json!(20180101)),
+                "{}",
+                date
+            );
+        }
+        assert_eq!(
+            call(transform_to_date_index, json!({"$date": "2018-01-01"})),
+            Ok(json!(20180101))
+        );
+        assert_eq!(call(transform_to_date_index, json!({})), Ok(json!(0)));
+    }
+
+    #[test]
+    fn dates_keep_the_written_time() {
+        let date = json!({"$date": "2018-01-01T23:30:00.5-05:00"});
+        assert_eq!(
+            call(transform_bson_date_to_datetime, date.clone()),
+            Ok(json!("2018-01-01T23:30:00.500000"))
+        );
+        assert_eq!(
+            call(transform_bson_date_to_time_index, date),
+            Ok(json!(233000))
+        );
+    }
+
+    #[test]
+    fn long_datetimes_accept_seconds_milliseconds_and_strings() {
+        let long = transform_convert_bson_long_datetime;
+        let expected = Ok(json!("2018-01-01 02:02:02"));
+        assert_eq!(call(long, json!(1514772122)), expected);
+        assert_eq!(call(long, json!("1514772122123")), expected);
+        assert_eq!(call(long, json!({"$date": 1514772122123i64})), expected);
+        assert_eq!(
+            call(long, json!({"$date": {"$numberLong": "1514772122123"}})),
+            expected
+        );
+        assert_eq!(
+            call(long, json!({"$date": "2018-01-01T02:02:02.9Z"})),
+            expected
+        );
+        assert_eq!(call(long, json!({"$numberLong": "1514772122"})), expected);
+        assert_eq!(call(long, json!(0)), Ok(Value::Null));
+        assert_eq!(type_name(call(long, json!("soon"))), "ValueError");
+    }
+
+    #[test]
+    fn malformed_dates_raise_like_python() {
+        assert_eq!(
+            type_name(call(transform_to_date_index, json!({"$date": "garbage"}))),
+            "ValueError"
+        );
+        assert_eq!(
+            type_name(call(transform_to_date_index, json!("2018-01-01"))),
+            "ValueError"
+        );
+        assert_eq!(
+            call(transform_to_date_index, json!({"$date": [1]})),
+            Ok(json!(0))
+        );
+    }
+}
@@ -0,0 +1,462 @@
+//! Date and timestamp helpers of FieldTransformer.
+//!
+//! Records arrive as JSON, so where the Python method takes a `datetime`
+//! the port also accepts the ISO 8601 string a `datetime` converts to
+//! (with `T` or a space as separator, optionally with a UTC offset);
+//! any other value fails with the `AttributeError` Python would raise.
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Timelike};
+use serde_json::{Map, Value};
+
+use super::{check_params, float_value, py_error, py_float, py_repr_str, py_type_name, truthy};
+use crate::error::TransformError;
+
+/// Largest timestamp taken to be in seconds rather than milliseconds.
+pub const MAX_SECOND_TIMESTAMP: f64 = 9_999_999_999.0;
+
+/// `YYYY-MM-DD HH:MM:SS`, followed by `.ffffff` when `keep_zero_microsecond`
+/// is set and the microsecond is non-zero.
+pub fn datetime_to_db_datetime(value: &NaiveDateTime, keep_zero_microsecond: bool) -> String {
+    let micros = value.nanosecond() / 1_000;
+    let seconds = format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        value.year(),
+        value.month(),
+        value.day(),
+        value.hour(),
+        value.minute(),
+        value.second()
+    );
+
+    if !keep_zero_microsecond || micros == 0 {
+        seconds
+    } else {
+        format!("{}.{:06}", seconds, micros)
+    }
+}
+
+/// `YYYYMMDD` as an integer.
+pub fn datetime_to_date_index(value: &NaiveDateTime) -> i64 {
+    i64::from(value.year()) * 10_000 + i64::from(value.month()) * 100 + i64::from(value.day())
+}
+
+/// `HHMMSS` as an integer.
+pub fn datetime_to_time_index(value: &NaiveDateTime) -> i64 {
+    i64::from(value.hour()) * 10_000 + i64::from(value.minute()) * 100 + i64::from(value.second())
+}
+
+/// Divide by 1000 until the timestamp fits in seconds.
+pub fn normalize_second_timestamp(mut timestamp: f64) -> f64 {
+    // Python loops forever on infinity; stop instead and let the caller
+    // reject the value.
+    while timestamp > MAX_SECOND_TIMESTAMP && timestamp.is_finite() {
+        timestamp /= 1000.0;
+    }
+    timestamp
+}
+
+/// Multiply by 1000 unless the timestamp is already in milliseconds.
+pub fn normalize_millisecond_timestamp(timestamp: f64) -> f64 {
+    if timestamp < MAX_SECOND_TIMESTAMP {
+        timestamp * 1000.0
+    } else {
+        timestamp
+    }
+}
+
+/// `datetime.utcfromtimestamp(timestamp)`, rounding to the nearest
+/// microsecond with ties to even.
+pub fn utc_from_timestamp(timestamp: f64) -> Result<NaiveDateTime, TransformError> {
+    if timestamp.is_nan() {
+        return Err(py_error("ValueError", "Invalid value NaN (not a number)"));
+    }
+    if timestamp.is_infinite() {
+        return Err(py_error(
+            "OverflowError",
+            "cannot convert float infinity to integer",
+        ));
+    }
+
+    let mut seconds = timestamp.trunc();
+    let mut micros = ((timestamp - seconds) * 1e6).round_ties_even();
+    if micros >= 1e6 {
+        seconds += 1.0;
+        micros -= 1e6;
+    } else if micros < 0.0 {
+        seconds -= 1.0;
+        micros += 1e6;
+    }
+
+    // `time_t`, then the `int` year of `gmtime`, then `datetime`'s years.
+    if !(i64::MIN as f64..=i64::MAX as f64).contains(&seconds) {
+        return Err(py_error(
+            "OverflowError",
+            "timestamp out of range for platform time_t",
+        ));
+    }
+    let year = civil_year((seconds as i64).div_euclid(86_400));
+    if i32::try_from(year - 1900).is_err() {
+        return Err(py_error(
+            "OSError",
+            "[Errno 75] Value too large for defined data type",
+        ));
+    }
+    let out_of_range = || py_error("ValueError", format!("year {} is out of range", year));
+    if !(1..=9999).contains(&year) {
+        return Err(out_of_range());
+    }
+
+    DateTime::from_timestamp(seconds as i64, micros as u32 * 1_000)
+        .map(|datetime| datetime.naive_utc())
+        .ok_or_else(out_of_range)
+}
+
+/// The proleptic Gregorian year of a day count since 1970-01-01.
+fn civil_year(days: i64) -> i64 {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Years start in March; January and February belong to the next.
+    let march_based_month = (5 * day_of_year + 2) / 153;
+    year_of_era + era * 400 + i64::from(march_based_month >= 10)
+}
+
+/// `datetime.isoformat()` of a naive datetime.
+pub fn isoformat(value: &NaiveDateTime) -> String {
+    let micros = value.nanosecond() / 1_000;
+    let seconds = value.format("%Y-%m-%dT%H:%M:%S");
+
+    if micros == 0 {
+        seconds.to_string()
+    } else {
+        format!("{}.{:06}", seconds, micros)
+    }
+}
+
+/// `datetime.isoformat() + "Z"`.
+pub fn to_iso_8601(value: &NaiveDateTime) -> String {
+    format!("{}Z", isoformat(value))
+}
+
+/// `datetime.strptime(value, format)` for the directives FieldTransformer
+/// formats use: `%Y`, `%m`, `%d`, `%H`, `%M`, `%S`, `%f` and `%%`. Any
+/// other directive, or a stray `%`, is a `ValueError` like a bad directive
+/// is in Python.
+pub fn strptime(value: &str, format: &str) -> Result<NaiveDateTime, TransformError> {
+    let mismatch = || {
+        py_error(
+            "ValueError",
+            format!(
+                "time data {} does not match format {}",
+                py_repr_str(value),
+                py_repr_str(format)
+            ),
+        )
+    };
+
+    let mut fields = [1900, 1, 1, 0, 0, 0, 0];
+    let mut rest = value;
+    let mut directives = format.chars();
+    while let Some(c) = directives.next() {
+        if c != '%' {
+            let mut chars = rest.chars();
+            match chars.next() {
+                Some(r) if r.to_lowercase().eq(c.to_lowercase()) => rest = chars.as_str(),
+                _ => return Err(mismatch()),
+            }
+            continue;
+        }
+
+        let (field, min, max, range) = match directives.next() {
+            Some('%') => {
+                rest = rest.strip_prefix('%').ok_or_else(mismatch)?;
+                continue;
+            }
+            Some('Y') => (0, 4, 4, 0..=9999),
+            Some('m') => (1, 1, 2, 1..=12),
+            Some('d') => (2, 1, 2, 1..=31),
+            Some('H') => (3, 1, 2, 0..=23),
+            Some('M') => (4, 1, 2, 0..=59),
+            Some('S') => (5, 1, 2, 0..=61),
+            Some('f') => (6, 1, 6, 0..=999_999),
+            Some(other) => {
+                return Err(py_error(
+                    "ValueError",
+                    format!(
+                        "'{}' is a bad directive in format {}",
+                        other,
+                        py_repr_str(format)
+                    ),
+                ))
+            }
+            None => {
+                return Err(py_error(
+                    "ValueError",
+                    format!("stray % in format {}", py_repr_str(format)),
+                ))
+            }
+        };
+        let count = rest
+            .bytes()
+            .take(max)
+            .take_while(u8::is_ascii_digit)
+            .count();
+        if count < min {
+            return Err(mismatch());
+        }
+        let digits = &rest[..count];
+        let mut number: u32 = digits.parse().map_err(|_| mismatch())?;
+        if !range.contains(&number) {
+            return Err(mismatch());
+        }
+        if field == 6 {
+            number *= 10u32.pow(6 - count as u32);
+        }
+        fields[field] = number;
+        rest = &rest[count..];
+    }
+    if !rest.is_empty() {
+        return Err(py_error(
+            "ValueError",
+            format!("unconverted data remains: {}", rest),
+        ));
+    }
+
+    let [year, month, day, hour, minute, second, micros] = fields;
+    if year == 0 {
+        return Err(py_error("ValueError", "year 0 is out of range"));
+    }
+    let date = NaiveDate::from_ymd_opt(year as i32, month, day)
+        .ok_or_else(|| py_error("ValueError", "day is out of range for month"))?;
+    if second > 59 {
+        return Err(py_error("ValueError", "second must be in 0..59"));
+    }
+    Ok(date
+        .and_hms_micro_opt(hour, minute, second, micros)
+        .expect("fields are range checked"))
+}
+
+/// `FieldTransformer.date_string_to_datetime`: ISO 8601 with microseconds,
+/// falling back to whole seconds.
+pub fn date_string_to_datetime(value: &str) -> Result<NaiveDateTime, TransformError> {
+    strptime(value, "%Y-%m-%dT%H:%M:%S.%f").or_else(|_| strptime(value, "%Y-%m-%dT%H:%M:%S"))
+}
+
+/// Parse the ISO 8601 form of a `datetime`, keeping its local fields when
+/// it has an offset, like the attributes of an aware `datetime` do.
+pub fn parse_datetime(value: &str) -> Option<NaiveDateTime> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Some(datetime.naive_local());
+    }
+
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+}
+
+/// A `datetime` argument; `attribute` is the first one the Python method
+/// reads, named in the error for anything else.
+fn datetime_arg(value: &Value, attribute: &str) -> Result<NaiveDateTime, TransformError> {
+    let parsed = match value {
+        Value::String(s) => parse_datetime(s),
+        _ => None,
+    };
+
+    parsed.ok_or_else(|| {
+        py_error(
+            "AttributeError",
+            format!(
+                "'{}' object has no attribute '{}'",
+                py_type_name(value),
+                attribute
+            ),
+        )
+    })
+}
+
+/// `unix_timestamp_to_*`: None for a falsy value, otherwise the UTC
+/// datetime of the normalised timestamp.
+fn unix_timestamp_arg(value: &Value) -> Result<Option<NaiveDateTime>, TransformError> {
+    if !truthy(value) {
+        return Ok(None);
+    }
+
+    let timestamp = normalize_second_timestamp(py_float(value)?);
+    utc_from_timestamp(timestamp).map(Some)
+}
+
+pub(crate) fn transform_datetime_to_db_datetime(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params(
+        "datetime_to_db_datetime",
+        params,
+        &["keep_zero_microsecond"],
+    )?;
+    let keep_zero_microsecond = params.get("keep_zero_microsecond").is_none_or(truthy);
+    let attribute = if keep_zero_microsecond {
+        "microsecond"
+    } else {
+        "year"
+    };
+
+    let datetime = datetime_arg(value, attribute)?;
+    Ok(Value::String(datetime_to_db_datetime(
+        &datetime,
+        keep_zero_microsecond,
+    )))
+}
+
+pub(crate) fn transform_datetime_to_date_index(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("datetime_to_date_index", params, &[])?;
+    let datetime = datetime_arg(value, "year")?;
+    Ok(datetime_to_date_index(&datetime).into())
+}
+
+pub(crate) fn transform_datetime_to_time_index(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("datetime_to_time_index", params, &[])?;
+    let datetime = datetime_arg(value, "hour")?;
+    Ok(datetime_to_time_index(&datetime).into())
+}
+
+pub(crate) fn transform_unix_timestamp_to_db_date_time(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("unix_timestamp_to_db_date_time", params, &[])?;
+    Ok(unix_timestamp_arg(value)?
+        .map(|datetime| Value::String(datetime_to_db_datetime(&datetime, true)))
+        .unwrap_or(Value::Null))
+}
+
+pub(crate) fn transform_unix_timestamp_to_date_index(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("unix_timestamp_to_date_index", params, &[])?;
+    Ok(unix_timestamp_arg(value)?
+        .map(|datetime| datetime_to_date_index(&datetime).into())
+        .unwrap_or(Value::Null))
+}
+
+pub(crate) fn transform_unix_timestamp_to_time_index(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("unix_timestamp_to_time_index", params, &[])?;
+    Ok(unix_timestamp_arg(value)?
+        .map(|datetime| datetime_to_time_index(&datetime).into())
+        .unwrap_or(Value::Null))
+}
+
+pub(crate) fn transform_normalize_second_timestamp(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("normalize_second_timestamp", params, &[])?;
+    float_value(normalize_second_timestamp(py_float(value)?))
+}
+
+pub(crate) fn transform_normalize_millisecond_timestamp(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("normalize_millisecond_timestamp", params, &[])?;
+    float_value(normalize_millisecond_timestamp(py_float(value)?))
+}
+
+pub(crate) fn transform_epoch_to_iso_8601(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("epoch_to_iso_8601", params, &[])?;
+    let date = subscript(value, "$date")?;
+    let timestamp = normalize_second_timestamp(py_float(date)?);
+    Ok(Value::String(to_iso_8601(&utc_from_timestamp(timestamp)?)))
+}
+
+/// `value[key]` for a string key.
+pub(crate) fn subscript<'a>(value: &'a Value, key: &str) -> Result<&'a Value, TransformError> {
+    match value {
+        Value::Object(map) => map
+            .get(key)
+            .ok_or_else(|| py_error("KeyError", format!("'{}'", key))),
+        Value::String(_) => Err(py_error(
+            "TypeError",
+            "string indices must be integers, not 'str'",
+        )),
+        Value::Array(_) => Err(py_error(
+            "TypeError",
+            "list indices must be integers or slices, not str",
+        )),
+        _ => Err(py_error(
+            "TypeError",
+            format!("'{}' object is not subscriptable", py_type_name(value)),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value_error(result: Result<NaiveDateTime, TransformError>) -> String {
+        match result {
+            Err(TransformError::Python {
+                type_name, message, ..
+            }) if type_name == "ValueError" => message,
+            other => panic!("expected a ValueError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn strptime_parses_the_supported_directives() {
+        let parsed = strptime("2018-01-01T02:03:04.5", "%Y-%m-%dT%H:%M:%S.%f").unwrap();
+        assert_eq!(isoformat(&parsed), "2018-01-01T02:03:04.500000");
+        let parsed = strptime("100%2018", "100%%%Y").unwrap();
+        assert_eq!(isoformat(&parsed), "2018-01-01T00:00:00");
+    }
+
+    #[test]
+    fn strptime_rejects_other_directives() {
+        assert_eq!(
+            value_error(strptime("Mon 2018", "%a %Y")),
+            "'a' is a bad directive in format '%a %Y'"
+        );
+        assert_eq!(
+            value_error(strptime("2018", "%Y%")),
+            "stray % in format '%Y%'"
+        );
+    }
+
+    #[test]
+    fn strptime_reports_mismatches_like_python() {
+        assert_eq!(
+            value_error(strptime("2018-01", "%Y-%m-%d")),
+            "time data '2018-01' does not match format '%Y-%m-%d'"
+        );
+        assert_eq!(
+            value_error(strptime("2018-01-01x", "%Y-%m-%d")),
+            "unconverted data remains: x"
+        );
+        assert_eq!(
+            value_error(strptime("2018-02-30", "%Y-%m-%d")),
+            "day is out of range for month"
+        );
+    }
+}
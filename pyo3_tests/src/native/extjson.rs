@@ -0,0 +1,359 @@
+//! MongoDB Extended JSON payloads as typed values.
+//!
+//! Accepts both output modes of the Extended JSON v2 spec, plus the
+//! integer `$date` that older `mongoexport` versions write:
+//!
+//! * `{"$oid": "5a4a9b1a2f4c3b0001a1b2c3"}` as an `ObjectId`;
+//! * `{"$date": {"$numberLong": "1514772122123"}}` (canonical),
+//!   `{"$date": "2018-01-01T02:02:02.123Z"}` (relaxed) and
+//!   `{"$date": 1514772122123}` (legacy) as a `BsonDate`;
+//! * `{"$numberLong": "42"}` through `parse_number_long`.
+//!
+//! Malformed wrappers fail with a `ValueError`, like the Python helpers
+//! they replace.
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeDelta};
+use serde_json::{Map, Value};
+
+use super::{datetime::utc_from_timestamp, py_error, py_repr_str, py_type_name};
+use crate::error::TransformError;
+
+/// A 12-byte BSON ObjectId.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectId([u8; 12]);
+
+impl ObjectId {
+    /// Parse the 24 hex digits of an `$oid`.
+    pub fn parse_str(value: &str) -> Result<Self, TransformError> {
+        let invalid = || {
+            py_error(
+                "ValueError",
+                format!("invalid ObjectId {}", py_repr_str(value)),
+            )
+        };
+        // `from_str_radix` alone would take a `+` sign as a digit pair.
+        if value.len() != 24 || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+
+        let mut bytes = [0; 12];
+        for (byte, pair) in bytes.iter_mut().zip(value.as_bytes().chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+        }
+        Ok(ObjectId(bytes))
+    }
+
+    pub fn bytes(&self) -> [u8; 12] {
+        self.0
+    }
+
+    /// Creation time embedded in the id, in seconds since the Unix epoch.
+    pub fn timestamp(&self) -> u32 {
+        u32::from_be_bytes([self.0[0], self.0[1], self.0[2], self.0[3]])
+    }
+
+    /// Lowercase hex, as `str(ObjectId)` prints it.
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+/// The payload of a `$date`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BsonDate {
+    /// Milliseconds since the Unix epoch: canonical `{"$numberLong": ...}`
+    /// or a legacy bare integer.
+    Millis(i64),
+    /// Relaxed ISO 8601 string; a string without an offset is UTC.
+    Iso(DateTime<FixedOffset>),
+}
+
+impl BsonDate {
+    /// Decode the value of a `$date` key.
+    pub fn from_ext(payload: &Value) -> Result<Self, TransformError> {
+        match payload {
+            Value::String(s) => parse_date_string(s).map(BsonDate::Iso),
+            Value::Number(n) => n
+                .as_i64()
+                .map(BsonDate::Millis)
+                .ok_or_else(|| py_error("ValueError", format!("invalid $date value {}", payload))),
+            Value::Object(map) => match single_key(map, "$numberLong") {
+                Some(Value::String(s)) => parse_number_long(s).map(BsonDate::Millis),
+                _ => Err(py_error(
+                    "ValueError",
+                    format!("invalid $date value {}", payload),
+                )),
+            },
+            _ => Err(py_error(
+                "ValueError",
+                format!("$date must not be a '{}'", py_type_name(payload)),
+            )),
+        }
+    }
+
+    /// Wall-clock fields of the date, as the Python helpers see them: UTC
+    /// for epoch milliseconds, the written local time for ISO strings.
+    pub fn wall_time(&self) -> Result<NaiveDateTime, TransformError> {
+        match self {
+            BsonDate::Millis(millis) => utc_from_timestamp(*millis as f64 / 1000.0),
+            BsonDate::Iso(datetime) => Ok(datetime.naive_local()),
+        }
+    }
+
+    /// Seconds since the Unix epoch.
+    pub fn timestamp(&self) -> f64 {
+        match self {
+            BsonDate::Millis(millis) => *millis as f64 / 1000.0,
+            BsonDate::Iso(datetime) => {
+                datetime.timestamp() as f64 + f64::from(datetime.timestamp_subsec_micros()) / 1e6
+            }
+        }
+    }
+}
+
+fn single_key<'a>(map: &'a Map<String, Value>, key: &str) -> Option<&'a Value> {
+    (map.len() == 1).then(|| map.get(key)).flatten()
+}
+
+/// The decimal string of a `$numberLong`.
+pub fn parse_number_long(value: &str) -> Result<i64, TransformError> {
+    value.parse().map_err(|_| {
+        py_error(
+            "ValueError",
+            format!("invalid $numberLong {}", py_repr_str(value)),
+        )
+    })
+}
+
+/// A relaxed `$date` string: `YYYY-MM-DD`, optionally followed by `T` or a
+/// space and `HH:MM:SS[.fraction]`, then an optional `Z` or `±HH[:MM]`
+/// offset. Fractions beyond microseconds are truncated.
+pub fn parse_date_string(value: &str) -> Result<DateTime<FixedOffset>, TransformError> {
+    parse_iso(value).ok_or_else(|| {
+        py_error(
+            "ValueError",
+            format!("invalid $date string {}", py_repr_str(value)),
+        )
+    })
+}
+
+fn parse_iso(value: &str) -> Option<DateTime<FixedOffset>> {
+    let mut scanner = Scanner(value.as_bytes());
+
+    let year = scanner.digits(4, 4)?;
+    scanner.expect(b'-')?;
+    let month = scanner.digits(1, 2)?;
+    scanner.expect(b'-')?;
+    let day = scanner.digits(1, 2)?;
+    let date = chrono::NaiveDate::from_ymd_opt(year as i32, month, day)?;
+
+    let mut time = (0, 0, 0, 0);
+    if scanner.eat(b'T') || scanner.eat(b't') || scanner.eat(b' ') {
+        let hour = scanner.digits(1, 2)?;
+        scanner.expect(b':')?;
+        let minute = scanner.digits(1, 2)?;
+        scanner.expect(b':')?;
+        let second = scanner.digits(1, 2)?;
+        let mut micros = 0;
+        if scanner.eat(b'.') {
+            let start = scanner.0;
+            let count = start.iter().take_while(|b| b.is_ascii_digit()).count();
+            if count == 0 {
+                return None;
+            }
+            let fraction = std::str::from_utf8(&start[..count.min(6)]).ok()?;
+            micros = fraction.parse::<u32>().ok()? * 10u32.pow(6 - fraction.len() as u32);
+            scanner.0 = &start[count..];
+        }
+        time = (hour, minute, second, micros);
+    }
+    let naive = date.and_hms_micro_opt(time.0, time.1, time.2, time.3)?;
+
+    let offset = if scanner.0.is_empty() || scanner.eat(b'Z') || scanner.eat(b'z') {
+        0
+    } else {
+        let sign = if scanner.eat(b'+') {
+            1
+        } else if scanner.eat(b'-') {
+            -1
+        } else {
+            return None;
+        };
+        let hours = scanner.digits(2, 2)?;
+        scanner.eat(b':');
+        let minutes = if scanner.0.is_empty() {
+            0
+        } else {
+            scanner.digits(2, 2)?
+        };
+        sign * (hours * 3600 + minutes * 60) as i32
+    };
+    if !scanner.0.is_empty() {
+        return None;
+    }
+
+    let offset = FixedOffset::east_opt(offset)?;
+    let utc = naive.checked_sub_signed(TimeDelta::seconds(i64::from(offset.local_minus_utc())))?;
+    Some(DateTime::from_naive_utc_and_offset(utc, offset))
+}
+
+struct Scanner<'a>(&'a [u8]);
+
+impl Scanner<'_> {
+    fn eat(&mut self, byte: u8) -> bool {
+        match self.0.split_first() {
+            Some((first, rest)) if *first == byte => {
+                self.0 = rest;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.eat(byte).then_some(())
+    }
+
+    fn digits(&mut self, min: usize, max: usize) -> Option<u32> {
+        let count = self
+            .0
+            .iter()
+            .take(max)
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        if count < min {
+            return None;
+        }
+        let (digits, rest) = self.0.split_at(count);
+        self.0 = rest;
+        std::str::from_utf8(digits).ok()?.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use serde_json::json;
+
+    use super::*;
+
+    fn wall_time(payload: Value) -> NaiveDateTime {
+        BsonDate::from_ext(&payload).unwrap().wall_time().unwrap()
+    }
+
+    fn at(hour: u32, minute: u32, second: u32, micro: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2018, 1, 1)
+            .unwrap()
+            .and_hms_micro_opt(hour, minute, second, micro)
+            .unwrap()
+    }
+
+    fn value_error(result: Result<impl std::fmt::Debug, TransformError>) -> String {
+        match result {
+            Err(TransformError::Python {
+                type_name, message, ..
+            }) if type_name == "ValueError" => message,
+            other => panic!("expected a ValueError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn object_ids_parse_from_hex() {
+        let oid = ObjectId::parse_str("5A4A9B1A2F4C3B0001A1B2C3").unwrap();
+        assert_eq!(oid.to_hex(), "5a4a9b1a2f4c3b0001a1b2c3");
+        assert_eq!(oid.timestamp(), 0x5a4a9b1a);
+        assert_eq!(oid.bytes()[11], 0xc3);
+    }
+
+    #[test]
+    fn object_ids_need_24_hex_digits() {
+        assert_eq!(
+            value_error(ObjectId::parse_str("5a4a9b1a")),
+            "invalid ObjectId '5a4a9b1a'"
+        );
+        assert_eq!(
+            value_error(ObjectId::parse_str("5a4a9b1a2f4c3b0001a1b2cg")),
+            "invalid ObjectId '5a4a9b1a2f4c3b0001a1b2cg'"
+        );
+        assert!(ObjectId::parse_str("5a4a9b1a2f4c3b0001a1b2é").is_err());
+        assert_eq!(
+            value_error(ObjectId::parse_str("+a+a+a+a+a+a+a+a+a+a+a+a")),
+            "invalid ObjectId '+a+a+a+a+a+a+a+a+a+a+a+a'"
+        );
+    }
+
+    #[test]
+    fn numeric_dates_are_epoch_milliseconds() {
+        let expected = at(2, 2, 2, 123_000);
+        assert_eq!(wall_time(json!(1514772122123i64)), expected);
+        assert_eq!(wall_time(json!({"$numberLong": "1514772122123"})), expected);
+        assert_eq!(
+            BsonDate::from_ext(&json!(1514772122123i64))
+                .unwrap()
+                .timestamp(),
+            1514772122.123
+        );
+    }
+
+    #[test]
+    fn date_strings_take_every_relaxed_shape() {
+        assert_eq!(
+            wall_time(json!("2018-01-01T02:02:02.123Z")),
+            at(2, 2, 2, 123_000)
+        );
+        assert_eq!(wall_time(json!("2018-01-01 02:02:02")), at(2, 2, 2, 0));
+        assert_eq!(wall_time(json!("2018-01-01")), at(0, 0, 0, 0));
+        assert_eq!(
+            wall_time(json!("2018-01-01T02:02:02.1234567")),
+            at(2, 2, 2, 123_456)
+        );
+    }
+
+    #[test]
+    fn offsets_keep_the_written_time() {
+        for date in [
+            "2018-01-01T02:02:02+07:00",
+            "2018-01-01T02:02:02+0700",
+            "2018-01-01T02:02:02+07",
+        ] {
+            let parsed = BsonDate::from_ext(&json!(date)).unwrap();
+            assert_eq!(parsed.wall_time(), Ok(at(2, 2, 2, 0)), "{}", date);
+            assert_eq!(parsed.timestamp(), 1514772122.0 - 7.0 * 3600.0, "{}", date);
+        }
+        let west = BsonDate::from_ext(&json!("2018-01-01T02:02:02-05:30")).unwrap();
+        assert_eq!(west.timestamp(), 1514772122.0 + 5.5 * 3600.0);
+    }
+
+    #[test]
+    fn invalid_dates_are_value_errors() {
+        for date in [
+            "2018-13-01",
+            "2018-01-01T02:02",
+            "2018-01-01T02:02:02.",
+            "2018-01-01T02:02:02 UTC",
+            "18-01-01",
+        ] {
+            assert_eq!(
+                value_error(BsonDate::from_ext(&json!(date))),
+                format!("invalid $date string '{}'", date)
+            );
+        }
+        assert_eq!(
+            value_error(BsonDate::from_ext(&json!(1.5))),
+            "invalid $date value 1.5"
+        );
+        assert_eq!(
+            value_error(BsonDate::from_ext(&json!({"$numberLong": "1", "x": 1}))),
+            "invalid $date value {\"$numberLong\":\"1\",\"x\":1}"
+        );
+        assert_eq!(
+            value_error(BsonDate::from_ext(&json!(true))),
+            "$date must not be a 'bool'"
+        );
+        assert_eq!(
+            value_error(parse_number_long("12x")),
+            "invalid $numberLong '12x'"
+        );
+    }
+}
@@ -0,0 +1,32 @@
+//! Hardware-log transforms.
+//!
+//! The CRC32 → app name table is `data/crc32_app_names.csv`, compiled in;
+//! a spec can map through another copy of it with a `lookup` table.
+
+use std::sync::OnceLock;
+
+use serde_json::{Map, Value};
+
+use super::{check_params, py_str};
+use crate::{error::TransformError, lookup::Table};
+
+const EMBEDDED_APP_NAMES: &str = include_str!("../../data/crc32_app_names.csv");
+
+/// App names by the CRC32 of their node name, in decimal.
+pub fn app_names() -> &'static Table {
+    static APP_NAMES: OnceLock<Table> = OnceLock::new();
+    APP_NAMES.get_or_init(|| {
+        Table::from_csv(EMBEDDED_APP_NAMES, None, None).expect("embedded app names are valid")
+    })
+}
+
+pub(crate) fn transform_crc32_to_app_name(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("crc32_to_app_name", params, &[])?;
+    Ok(app_names()
+        .get(&py_str(value))
+        .cloned()
+        .unwrap_or(Value::Null))
+}
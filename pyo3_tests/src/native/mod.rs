@@ -0,0 +1,366 @@
+//! Rust ports of FieldTransformer methods.
+//!
+//! Each port produces exactly what the Python method returns once it has
+//! gone through `convert`, so an engine can answer `transform` calls for
+//! these methods without entering the interpreter. Where the Python method
+//! would raise, the port returns `TransformError::Python` with the same
+//! exception type, keeping error categories identical on both paths.
+
+pub mod ate;
+pub mod brand;
+pub mod bson;
+pub mod datetime;
+pub mod extjson;
+pub mod hwlog;
+pub mod serial;
+
+use serde_json::{Map, Number, Value};
+
+use crate::error::TransformError;
+
+/// A native FieldTransformer method: `(value, **params)`.
+pub type NativeFn = fn(&Value, &Map<String, Value>) -> Result<Value, TransformError>;
+
+const METHODS: &[(&str, NativeFn)] = &[
+    (
+        "datetime_to_db_datetime",
+        datetime::transform_datetime_to_db_datetime,
+    ),
+    (
+        "datetime_to_date_index",
+        datetime::transform_datetime_to_date_index,
+    ),
+    (
+        "datetime_to_time_index",
+        datetime::transform_datetime_to_time_index,
+    ),
+    (
+        "unix_timestamp_to_db_date_time",
+        datetime::transform_unix_timestamp_to_db_date_time,
+    ),
+    (
+        "unix_timestamp_to_date_index",
+        datetime::transform_unix_timestamp_to_date_index,
+    ),
+    (
+        "unix_timestamp_to_time_index",
+        datetime::transform_unix_timestamp_to_time_index,
+    ),
+    (
+        "normalize_second_timestamp",
+        datetime::transform_normalize_second_timestamp,
+    ),
+    (
+        "normalize_millisecond_timestamp",
+        datetime::transform_normalize_millisecond_timestamp,
+    ),
+    ("epoch_to_iso_8601", datetime::transform_epoch_to_iso_8601),
+    (
+        "convert_bson_object_id",
+        bson::transform_convert_bson_object_id,
+    ),
+    (
+        "convert_bson_long_datetime",
+        bson::transform_convert_bson_long_datetime,
+    ),
+    (
+        "convert_bson_rfc3339_datetime",
+        bson::transform_convert_bson_rfc3339_datetime,
+    ),
+    ("to_date_index", bson::transform_to_date_index),
+    (
+        "bson_date_to_datetime",
+        bson::transform_bson_date_to_datetime,
+    ),
+    (
+        "bson_date_to_time_index",
+        bson::transform_bson_date_to_time_index,
+    ),
+    (
+        "serial_number_to_product_code",
+        serial::transform_serial_number_to_product_code,
+    ),
+    (
+        "serial_number_to_unique_identifier",
+        serial::transform_serial_number_to_unique_identifier,
+    ),
+    (
+        "serial_number_to_owner",
+        serial::transform_serial_number_to_owner,
+    ),
+    (
+        "extract_device_type_from_sn_prefix",
+        serial::transform_extract_device_type_from_sn_prefix,
+    ),
+    ("extract_ate_id", ate::transform_extract_ate_id),
+    ("crc32_to_app_name", hwlog::transform_crc32_to_app_name),
+    ("normalize_brand_id", brand::transform_normalize_brand_id),
+    (
+        "transform_db_name_to_brand",
+        brand::transform_db_name_to_brand,
+    ),
+    (
+        "transform_project_name_to_brand",
+        brand::transform_project_name_to_brand,
+    ),
+    ("extract_sku", serial::transform_extract_sku),
+    ("is_dummy_device", serial::transform_is_dummy_device),
+];
+
+/// The native port of `method_name`, if there is one.
+pub fn lookup(method_name: &str) -> Option<NativeFn> {
+    METHODS
+        .iter()
+        .find(|(name, _)| *name == method_name)
+        .map(|(_, f)| *f)
+}
+
+/// Names of all methods with a native port.
+pub fn methods() -> impl Iterator<Item = &'static str> {
+    METHODS.iter().map(|(name, _)| *name)
+}
+
+/// A Python exception raised by a native port.
+pub(crate) fn py_error(type_name: &str, message: impl Into<String>) -> TransformError {
+    TransformError::Python {
+        type_name: type_name.to_owned(),
+        message: message.into(),
+        traceback: String::new(),
+    }
+}
+
+/// Name of the Python type `value` converts to.
+pub(crate) fn py_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "NoneType",
+        Value::Bool(_) => "bool",
+        Value::Number(n) if n.is_f64() => "float",
+        Value::Number(_) => "int",
+        Value::String(_) => "str",
+        Value::Array(_) => "list",
+        Value::Object(_) => "dict",
+    }
+}
+
+/// Python truthiness.
+pub(crate) fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|f| f != 0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+/// Python `float(value)`.
+pub(crate) fn py_float(value: &Value) -> Result<f64, TransformError> {
+    match value {
+        Value::Bool(b) => Ok(if *b { 1.0 } else { 0.0 }),
+        Value::Number(n) => Ok(n.as_f64().unwrap_or(f64::NAN)),
+        Value::String(s) => parse_float(s).ok_or_else(|| {
+            py_error(
+                "ValueError",
+                format!("could not convert string to float: {}", py_repr_str(s)),
+            )
+        }),
+        _ => Err(py_error(
+            "TypeError",
+            format!(
+                "float() argument must be a string or a real number, not '{}'",
+                py_type_name(value)
+            ),
+        )),
+    }
+}
+
+fn parse_float(s: &str) -> Option<f64> {
+    let s = s.trim();
+    if s.contains('_') {
+        // Python accepts single underscores between digits only.
+        let bytes = s.as_bytes();
+        let valid = bytes.iter().enumerate().all(|(i, b)| {
+            *b != b'_'
+                || (i > 0
+                    && i + 1 < bytes.len()
+                    && bytes[i - 1].is_ascii_digit()
+                    && bytes[i + 1].is_ascii_digit())
+        });
+        return if valid {
+            s.replace('_', "").parse().ok()
+        } else {
+            None
+        };
+    }
+
+    s.parse().ok()
+}
+
+/// Python `int(value)` for a str: surrounding whitespace, a sign and
+/// single underscores between digits are allowed.
+pub(crate) fn py_int_str(value: &str) -> Result<i128, TransformError> {
+    let invalid = || {
+        py_error(
+            "ValueError",
+            format!(
+                "invalid literal for int() with base 10: {}",
+                py_repr_str(value)
+            ),
+        )
+    };
+
+    let s = value.trim();
+    let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+    if digits.is_empty()
+        || digits.starts_with('_')
+        || digits.ends_with('_')
+        || digits.contains("__")
+        || !digits.bytes().all(|b| b.is_ascii_digit() || b == b'_')
+    {
+        return Err(invalid());
+    }
+    s.replace('_', "").parse().map_err(|_| invalid())
+}
+
+/// Python `key in value` for a str key.
+pub(crate) fn py_contains(value: &Value, key: &str) -> Result<bool, TransformError> {
+    match value {
+        Value::Object(map) => Ok(map.contains_key(key)),
+        Value::Array(items) => Ok(items.iter().any(|item| item.as_str() == Some(key))),
+        Value::String(s) => Ok(s.contains(key)),
+        _ => Err(py_error(
+            "TypeError",
+            format!("argument of type '{}' is not iterable", py_type_name(value)),
+        )),
+    }
+}
+
+/// Python `str(value)`.
+pub(crate) fn py_str(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        _ => py_repr(value),
+    }
+}
+
+/// Python `repr(value)`.
+pub(crate) fn py_repr(value: &Value) -> String {
+    match value {
+        Value::Null => "None".to_owned(),
+        Value::Bool(true) => "True".to_owned(),
+        Value::Bool(false) => "False".to_owned(),
+        Value::Number(n) => match (n.is_f64(), n.as_f64()) {
+            (true, Some(f)) => py_float_repr(f),
+            _ => n.to_string(),
+        },
+        Value::String(s) => py_repr_str(s),
+        Value::Array(items) => {
+            let items: Vec<_> = items.iter().map(py_repr).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(map) => {
+            let items: Vec<_> = map
+                .iter()
+                .map(|(key, value)| format!("{}: {}", py_repr_str(key), py_repr(value)))
+                .collect();
+            format!("{{{}}}", items.join(", "))
+        }
+    }
+}
+
+/// Python `repr(f)`: the shortest round-tripping digits, in scientific
+/// notation outside `1e-4 <= |f| < 1e16`.
+pub(crate) fn py_float_repr(f: f64) -> String {
+    if f.is_nan() {
+        return "nan".to_owned();
+    }
+    if f.is_infinite() {
+        return if f > 0.0 { "inf" } else { "-inf" }.to_owned();
+    }
+
+    let scientific = format!("{:e}", f);
+    let (mantissa, exponent) = scientific.split_once('e').expect("{:e} has an exponent");
+    let exponent: i32 = exponent.parse().expect("{:e} exponent is an integer");
+    if (-4..16).contains(&exponent) {
+        let positional = format!("{}", f);
+        if positional.contains('.') {
+            positional
+        } else {
+            format!("{}.0", positional)
+        }
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+    }
+}
+
+/// `repr()` of a Python str, close enough for error messages.
+pub(crate) fn py_repr_str(s: &str) -> String {
+    let quote = if s.contains('\'') && !s.contains('"') {
+        '"'
+    } else {
+        '\''
+    };
+
+    let mut repr = String::with_capacity(s.len() + 2);
+    repr.push(quote);
+    for c in s.chars() {
+        match c {
+            '\\' => repr.push_str("\\\\"),
+            '\n' => repr.push_str("\\n"),
+            '\r' => repr.push_str("\\r"),
+            '\t' => repr.push_str("\\t"),
+            c if c == quote => {
+                repr.push('\\');
+                repr.push(c);
+            }
+            c if c.is_ascii_graphic() || c == ' ' || (!c.is_ascii() && py_printable(c)) => {
+                repr.push(c)
+            }
+            c if (c as u32) < 0x100 => repr.push_str(&format!("\\x{:02x}", c as u32)),
+            c if (c as u32) < 0x10000 => repr.push_str(&format!("\\u{:04x}", c as u32)),
+            c => repr.push_str(&format!("\\U{:08x}", c as u32)),
+        }
+    }
+    repr.push(quote);
+    repr
+}
+
+/// `str.isprintable()` of a non-ASCII character: not a control, format,
+/// separator or private-use character. Unassigned code points are taken
+/// as printable.
+fn py_printable(c: char) -> bool {
+    !(c.is_control()
+        || matches!(c,
+            '\u{a0}' | '\u{ad}' | '\u{600}'..='\u{605}' | '\u{61c}' | '\u{6dd}' | '\u{70f}'
+            | '\u{1680}' | '\u{180e}' | '\u{2000}'..='\u{200f}' | '\u{2028}'..='\u{202f}'
+            | '\u{205f}'..='\u{2064}' | '\u{2066}'..='\u{206f}' | '\u{3000}'
+            | '\u{e000}'..='\u{f8ff}' | '\u{feff}' | '\u{fff9}'..='\u{fffb}'
+            | '\u{f0000}'..))
+}
+
+/// A float result, rejected like `convert::from_py` does when not finite.
+pub(crate) fn float_value(f: f64) -> Result<Value, TransformError> {
+    Number::from_f64(f)
+        .map(Value::Number)
+        .ok_or_else(|| TransformError::Conversion("float is not finite (float)".into()))
+}
+
+/// Reject keyword arguments `method_name` does not take, like Python does.
+pub(crate) fn check_params(
+    method_name: &str,
+    params: &Map<String, Value>,
+    accepted: &[&str],
+) -> Result<(), TransformError> {
+    match params.keys().find(|key| !accepted.contains(&key.as_str())) {
+        Some(key) => Err(py_error(
+            "TypeError",
+            format!(
+                "FieldTransformer.{}() got an unexpected keyword argument '{}'",
+                method_name, key
+            ),
+        )),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,377 @@
+//! Device serial numbers.
+//!
+//! `SerialNumber::parse` reads every attribute the serial-number methods
+//! derive from a serial in one pass; the transforms below wrap it with the
+//! input checks and errors of their Python counterparts. Which prefixes
+//! are trackers comes from `data/tracker_sn_prefixes.txt`, compiled in and
+//! used until another table, read with `TrackerPrefixes::load`, is
+//! installed.
+
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    path::Path,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use serde_json::{Map, Value};
+
+use super::{check_params, py_error, py_type_name, truthy};
+use crate::error::TransformError;
+
+const EMBEDDED_TRACKER_PREFIXES: &str = include_str!("../../data/tracker_sn_prefixes.txt");
+
+/// Length of the product code, also taken as the SKU.
+const PRODUCT_CODE_LEN: usize = 6;
+const UNIQUE_IDENTIFIER_LEN: usize = 4;
+
+static INSTALLED: RwLock<Option<Arc<TrackerPrefixes>>> = RwLock::new(None);
+
+/// Serial-number prefixes of trackers: one per line, `#` starting a
+/// comment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackerPrefixes(HashSet<String>);
+
+impl TrackerPrefixes {
+    /// The table compiled into the binary.
+    pub fn embedded() -> &'static TrackerPrefixes {
+        static EMBEDDED: OnceLock<TrackerPrefixes> = OnceLock::new();
+        EMBEDDED.get_or_init(|| TrackerPrefixes::parse(EMBEDDED_TRACKER_PREFIXES))
+    }
+
+    pub fn load(path: &Path) -> Result<TrackerPrefixes, TransformError> {
+        fs::read_to_string(path)
+            .map(|text| TrackerPrefixes::parse(&text))
+            .map_err(|err| TransformError::Load(format!("{}: {}", path.display(), err)))
+    }
+
+    pub fn parse(text: &str) -> TrackerPrefixes {
+        TrackerPrefixes(
+            text.lines()
+                .map(|line| line.split('#').next().unwrap_or_default().trim())
+                .filter(|prefix| !prefix.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        )
+    }
+
+    pub fn contains(&self, prefix: &str) -> bool {
+        self.0.contains(prefix)
+    }
+
+    /// The table the native serial-number transforms use.
+    pub fn installed() -> Arc<TrackerPrefixes> {
+        let installed = INSTALLED.read().unwrap_or_else(|err| err.into_inner());
+        if let Some(trackers) = installed.as_ref() {
+            return Arc::clone(trackers);
+        }
+        drop(installed);
+
+        let mut installed = INSTALLED.write().unwrap_or_else(|err| err.into_inner());
+        Arc::clone(installed.get_or_insert_with(|| Arc::new(TrackerPrefixes::embedded().clone())))
+    }
+
+    /// Make this the table of the native serial-number transforms, in every
+    /// engine of the process.
+    pub fn install(self) {
+        *INSTALLED.write().unwrap_or_else(|err| err.into_inner()) = Some(Arc::new(self));
+    }
+}
+
+/// What `extract_device_type_from_sn_prefix` classifies a prefix as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceType {
+    Tracker,
+    WearOs,
+    Hybrid,
+}
+
+impl DeviceType {
+    /// Classify a serial-number prefix. Anything that is not uppercase
+    /// alphanumeric is a hybrid.
+    pub fn from_prefix(prefix: &str, trackers: &TrackerPrefixes) -> DeviceType {
+        let well_formed = !prefix.is_empty()
+            && prefix
+                .bytes()
+                .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit());
+        if !well_formed {
+            DeviceType::Hybrid
+        } else if trackers.contains(prefix) {
+            DeviceType::Tracker
+        } else if prefix.starts_with(['K', 'C']) {
+            DeviceType::WearOs
+        } else {
+            DeviceType::Hybrid
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceType::Tracker => "Tracker",
+            DeviceType::WearOs => "WearOS",
+            DeviceType::Hybrid => "Hybrid",
+        }
+    }
+}
+
+impl fmt::Display for DeviceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Brand owning a device, from the second character of its serial.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Owner {
+    Citizen,
+    Fossil,
+}
+
+impl Owner {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Owner::Citizen => "citizen",
+            Owner::Fossil => "fossil",
+        }
+    }
+}
+
+impl fmt::Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The attributes of a serial number, e.g. `M0F30418M3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialNumber<'a> {
+    pub serial: &'a str,
+    /// The first six characters: `M0F304`.
+    pub sku: &'a str,
+    /// The SKU of a hybrid serial, one matching
+    /// `^[WZDLM][0-9][CDEFHJKLMRSTWXYZ][0-Z]{3}`.
+    pub product_code: Option<&'a str>,
+    /// Up to four characters after the product code of a hybrid serial:
+    /// `18M3`.
+    pub unique_identifier: Option<&'a str>,
+    /// None for serials shorter than two characters.
+    pub owner: Option<Owner>,
+    /// Device type of the SKU.
+    pub device_type: DeviceType,
+    /// Test devices: `TEST` or `INGTE` on the first line, in any case.
+    pub is_dummy: bool,
+}
+
+impl<'a> SerialNumber<'a> {
+    /// Parse `serial` against the installed tracker prefixes.
+    pub fn parse(serial: &'a str) -> SerialNumber<'a> {
+        SerialNumber::parse_with(serial, &TrackerPrefixes::installed())
+    }
+
+    pub fn parse_with(serial: &'a str, trackers: &TrackerPrefixes) -> SerialNumber<'a> {
+        let sku = char_slice(serial, 0, PRODUCT_CODE_LEN);
+        let hybrid = is_hybrid_product_code(serial);
+        let owner = serial.chars().nth(1).map(|c| match c {
+            '1' => Owner::Citizen,
+            _ => Owner::Fossil,
+        });
+
+        SerialNumber {
+            serial,
+            sku,
+            product_code: hybrid.then_some(sku),
+            unique_identifier: hybrid.then(|| {
+                char_slice(
+                    serial,
+                    PRODUCT_CODE_LEN,
+                    PRODUCT_CODE_LEN + UNIQUE_IDENTIFIER_LEN,
+                )
+            }),
+            owner,
+            device_type: DeviceType::from_prefix(sku, trackers),
+            is_dummy: is_dummy(serial),
+        }
+    }
+}
+
+fn is_hybrid_product_code(serial: &str) -> bool {
+    match serial.as_bytes() {
+        [family, generation, kind, rest @ ..] if rest.len() >= 3 => {
+            b"WZDLM".contains(family)
+                && generation.is_ascii_digit()
+                && b"CDEFHJKLMRSTWXYZ".contains(kind)
+                && rest[..3].iter().all(|b| (b'0'..=b'Z').contains(b))
+        }
+        _ => false,
+    }
+}
+
+/// `DUMMY_DEVICE.match(serial.upper())`, after Python's blank check.
+fn is_dummy(serial: &str) -> bool {
+    if serial.trim_matches(py_isspace).is_empty() {
+        return false;
+    }
+    let first_line = serial.split('\n').next().unwrap_or_default().to_uppercase();
+    first_line.contains("TEST") || first_line.contains("INGTE")
+}
+
+/// `str.isspace()` for one character.
+fn py_isspace(c: char) -> bool {
+    c.is_whitespace() || ('\x1c'..='\x1f').contains(&c)
+}
+
+/// `s[start:end]`, counting characters.
+fn char_slice(s: &str, start: usize, end: usize) -> &str {
+    let offset = |n| s.char_indices().nth(n).map_or(s.len(), |(i, _)| i);
+    &s[offset(start)..offset(end)]
+}
+
+pub(crate) fn transform_serial_number_to_product_code(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("serial_number_to_product_code", params, &[])?;
+    let product_code = value
+        .as_str()
+        .and_then(|serial| SerialNumber::parse(serial).product_code);
+    Ok(Value::String(product_code.unwrap_or("unknown").to_owned()))
+}
+
+pub(crate) fn transform_serial_number_to_unique_identifier(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("serial_number_to_unique_identifier", params, &[])?;
+    let unique_identifier = value
+        .as_str()
+        .and_then(|serial| SerialNumber::parse(serial).unique_identifier);
+    Ok(unique_identifier.map_or(Value::Null, |id| Value::String(id.to_owned())))
+}
+
+pub(crate) fn transform_serial_number_to_owner(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("serial_number_to_owner", params, &[])?;
+    if !truthy(value) {
+        return Ok(Value::Null);
+    }
+
+    // `value[1] == "1"`
+    let citizen = match value {
+        Value::String(serial) => match SerialNumber::parse(serial).owner {
+            Some(owner) => owner == Owner::Citizen,
+            None => return Err(py_error("IndexError", "string index out of range")),
+        },
+        Value::Array(items) => match items.get(1) {
+            Some(item) => item.as_str() == Some("1"),
+            None => return Err(py_error("IndexError", "list index out of range")),
+        },
+        Value::Object(_) => return Err(py_error("KeyError", "1")),
+        _ => {
+            return Err(py_error(
+                "TypeError",
+                format!("'{}' object is not subscriptable", py_type_name(value)),
+            ))
+        }
+    };
+
+    let owner = if citizen {
+        Owner::Citizen
+    } else {
+        Owner::Fossil
+    };
+    Ok(Value::String(owner.as_str().to_owned()))
+}
+
+pub(crate) fn transform_extract_device_type_from_sn_prefix(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("extract_device_type_from_sn_prefix", params, &[])?;
+    extract_device_type(value, &TrackerPrefixes::installed())
+}
+
+/// `extract_device_type_from_sn_prefix` against `trackers`.
+fn extract_device_type(value: &Value, trackers: &TrackerPrefixes) -> Result<Value, TransformError> {
+    let device_type = match value {
+        _ if !truthy(value) => DeviceType::Hybrid,
+        Value::String(prefix) => DeviceType::from_prefix(prefix, trackers),
+        _ => {
+            return Err(py_error(
+                "TypeError",
+                format!(
+                    "expected string or bytes-like object, got '{}'",
+                    py_type_name(value)
+                ),
+            ))
+        }
+    };
+    Ok(Value::String(device_type.as_str().to_owned()))
+}
+
+pub(crate) fn transform_extract_sku(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("extract_sku", params, &[])?;
+    Ok(match value {
+        Value::String(serial) => Value::String(SerialNumber::parse(serial).sku.to_owned()),
+        _ => Value::Null,
+    })
+}
+
+pub(crate) fn transform_is_dummy_device(
+    value: &Value,
+    params: &Map<String, Value>,
+) -> Result<Value, TransformError> {
+    check_params("is_dummy_device", params, &[])?;
+    match value {
+        Value::Null => Ok(Value::Bool(false)),
+        Value::String(serial) => Ok(Value::Bool(SerialNumber::parse(serial).is_dummy)),
+        _ => Err(py_error(
+            "AttributeError",
+            format!("'{}' object has no attribute 'strip'", py_type_name(value)),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn loaded_prefixes_are_trackers() {
+        let trackers = TrackerPrefixes::parse("# comment\nK9Z999  # trailing\n\n");
+        assert!(trackers.contains("K9Z999"));
+        assert_eq!(
+            DeviceType::from_prefix("K9Z999", TrackerPrefixes::embedded()),
+            DeviceType::WearOs
+        );
+        assert_eq!(
+            DeviceType::from_prefix("K9Z999", &trackers),
+            DeviceType::Tracker
+        );
+    }
+
+    #[test]
+    fn loaded_prefixes_change_the_device_type() {
+        let mut trackers = TrackerPrefixes::embedded().clone();
+        trackers.0.insert("K9Z998".to_owned());
+
+        let embedded = extract_device_type(&json!("K9Z998"), TrackerPrefixes::embedded());
+        assert_eq!(embedded, Ok(json!("WearOS")));
+        let loaded = extract_device_type(&json!("K9Z998"), &trackers);
+        assert_eq!(loaded, Ok(json!("Tracker")));
+        assert_eq!(
+            SerialNumber::parse_with("K9Z998ABCD", &trackers).device_type,
+            DeviceType::Tracker
+        );
+        assert_eq!(
+            extract_device_type(&json!(""), &trackers),
+            Ok(json!("Hybrid"))
+        );
+    }
+}
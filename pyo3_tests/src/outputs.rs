@@ -0,0 +1,141 @@
+//! Multi-output transforms: methods whose result is a tuple of several
+//! values, each with a name.
+//!
+//! A spec field can send those values to separate output fields instead
+//! of storing the tuple; see `FieldSpec::outputs`. The result is unpacked
+//! the same way whichever backend, or native port, produced it.
+
+use serde_json::{Map, Value};
+
+use crate::error::TransformError;
+
+/// A FieldTransformer method returning a tuple, with the name of each
+/// position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MultiOutput {
+    pub name: &'static str,
+    pub outputs: &'static [&'static str],
+}
+
+const METHODS: &[MultiOutput] = &[
+    MultiOutput {
+        name: "extract_ate_id",
+        outputs: &["category", "station", "factory", "station_number", "city"],
+    },
+    MultiOutput {
+        name: "extract_software_reset_cause_infomation",
+        outputs: &[
+            "software_reset_cause_key",
+            "information_key_1",
+            "information_col_1",
+            "information_col_1_hex",
+            "information_key_2",
+            "information_col_2",
+            "information_col_2_hex",
+        ],
+    },
+];
+
+/// The multi-output method `name`, if it is one.
+pub fn lookup(name: &str) -> Option<&'static MultiOutput> {
+    METHODS.iter().find(|method| method.name == name)
+}
+
+/// Name the values of `method`'s `result`.
+///
+/// A tuple (a JSON array) is named by the method's declared outputs; a
+/// dict (a JSON object) by its own keys, which is how methods from a
+/// custom module return several values without being declared here.
+pub fn unpack(method: &str, result: Value) -> Result<Map<String, Value>, TransformError> {
+    match (result, lookup(method)) {
+        (Value::Object(values), _) => Ok(values),
+        (Value::Array(values), Some(declared)) if values.len() == declared.outputs.len() => {
+            Ok(declared
+                .outputs
+                .iter()
+                .map(|output| (*output).to_owned())
+                .zip(values)
+                .collect())
+        }
+        (Value::Array(values), Some(declared)) => Err(TransformError::Conversion(format!(
+            "{} returned {} values, expected {}",
+            method,
+            values.len(),
+            declared.outputs.len()
+        ))),
+        (result, _) => Err(TransformError::Conversion(format!(
+            "{} returned {} where named outputs were expected",
+            method,
+            kind(&result)
+        ))),
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an undeclared tuple",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn tuples_are_named_by_the_declared_outputs() {
+        let values = unpack("extract_ate_id", json!(["DW", "MM", "FLEX", 1, "ZH"])).unwrap();
+        assert_eq!(
+            Value::Object(values),
+            json!({
+                "category": "DW",
+                "station": "MM",
+                "factory": "FLEX",
+                "station_number": 1,
+                "city": "ZH",
+            })
+        );
+    }
+
+    #[test]
+    fn dicts_are_named_by_their_keys() {
+        let values = unpack("custom_split", json!({"left": 1, "right": 2})).unwrap();
+        assert_eq!(Value::Object(values), json!({"left": 1, "right": 2}));
+    }
+
+    #[test]
+    fn tuples_must_have_every_declared_output() {
+        assert_eq!(
+            unpack("extract_ate_id", json!(["DW", "MM"])),
+            Err(TransformError::Conversion(
+                "extract_ate_id returned 2 values, expected 5".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn other_results_name_their_kind() {
+        let cases = [
+            ("extract_ate_id", Value::Null, "null"),
+            ("extract_ate_id", json!("DW"), "a string"),
+            ("extract_ate_id", json!(5), "a number"),
+            ("extract_ate_id", json!(true), "a boolean"),
+            ("custom_split", json!([1, 2]), "an undeclared tuple"),
+        ];
+        for (method, result, kind) in cases {
+            assert_eq!(
+                unpack(method, result),
+                Err(TransformError::Conversion(format!(
+                    "{} returned {} where named outputs were expected",
+                    method, kind
+                )))
+            );
+        }
+    }
+}
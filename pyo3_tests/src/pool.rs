@@ -0,0 +1,414 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use serde_json::Value;
+
+use crate::{
+    error::TransformError,
+    limits::Limit,
+    worker::{self, Request, Response, Task, WorkerConfig},
+};
+
+/// Environment variable overriding the worker executable.
+pub const WORKER_PROGRAM_ENV: &str = "FIELD_TRANSFORM_WORKER";
+
+const WORKER_PROGRAM: &str = "transform-worker";
+
+/// Time a worker gets past its configured `timeout_ms` before it is killed:
+/// the in-interpreter limit cannot interrupt a long C call.
+pub const TASK_TIMEOUT_GRACE: Duration = Duration::from_secs(1);
+
+type Outcome = Result<Value, TransformError>;
+
+pub struct WorkerPoolBuilder {
+    workers: usize,
+    queue_capacity: Option<usize>,
+    program: Option<PathBuf>,
+    config: WorkerConfig,
+    task_timeout: Option<Duration>,
+}
+
+impl Default for WorkerPoolBuilder {
+    fn default() -> Self {
+        WorkerPoolBuilder {
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            queue_capacity: None,
+            program: None,
+            config: WorkerConfig::default(),
+            task_timeout: None,
+        }
+    }
+}
+
+impl WorkerPoolBuilder {
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Maximum number of submitted records whose results have not been
+    /// received yet; `submit` blocks beyond it. Defaults to four per worker.
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = Some(capacity.max(1));
+        self
+    }
+
+    /// Worker executable. Defaults to `$FIELD_TRANSFORM_WORKER`, then to
+    /// `transform-worker` next to the current executable.
+    pub fn program(mut self, program: PathBuf) -> Self {
+        self.program = Some(program);
+        self
+    }
+
+    pub fn config(mut self, config: WorkerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Time a worker process gets to answer one task before it is killed
+    /// and replaced, and the task fails with `TransformError::Timeout`.
+    /// Defaults to the config's `timeout_ms` plus `TASK_TIMEOUT_GRACE`, and
+    /// to no deadline when that is unset too.
+    pub fn task_timeout(mut self, timeout: Duration) -> Self {
+        self.task_timeout = Some(timeout);
+        self
+    }
+
+    pub fn build(self) -> io::Result<WorkerPool> {
+        let capacity = self.queue_capacity.unwrap_or(self.workers * 4);
+        let (task_tx, task_rx) = mpsc::channel::<(u64, Task)>();
+        let (result_tx, result_rx) = mpsc::channel();
+        let (permit_tx, permit_rx) = mpsc::sync_channel(capacity);
+        let task_rx = Arc::new(Mutex::new(task_rx));
+
+        let program = match self.program {
+            Some(program) => program,
+            None => default_program()?,
+        };
+        let config = serde_json::to_string(&self.config)?;
+        let task_timeout = self.task_timeout.or_else(|| {
+            self.config
+                .timeout_ms
+                .map(|timeout_ms| Duration::from_millis(timeout_ms) + TASK_TIMEOUT_GRACE)
+        });
+        let handles = (0..self.workers)
+            .map(|_| {
+                let worker = ProcessWorker {
+                    program: program.clone(),
+                    config: config.clone(),
+                    task_timeout,
+                    process: None,
+                };
+                let (task_rx, result_tx) = (task_rx.clone(), result_tx.clone());
+                thread::spawn(move || worker.run(task_rx, result_tx))
+            })
+            .collect();
+
+        Ok(WorkerPool {
+            task_tx: Some(task_tx),
+            permit_tx,
+            submitted: AtomicU64::new(0),
+            delivery: Mutex::new(Delivery {
+                results: result_rx,
+                permits: permit_rx,
+                pending: BTreeMap::new(),
+                next: 0,
+            }),
+            handles,
+        })
+    }
+}
+
+struct Delivery {
+    results: Receiver<(u64, Outcome)>,
+    permits: Receiver<()>,
+    pending: BTreeMap<u64, Outcome>,
+    next: u64,
+}
+
+/// Shards records across `transform-worker` processes and hands results
+/// back in submission order.
+///
+/// Each worker process has its own interpreter and GIL, and is the only way
+/// to run transforms on several cores: sub-interpreters would need a
+/// per-interpreter GIL (Python 3.12+), which PyO3 does not support. Hosts
+/// that cannot spawn processes use one `Engine` instead.
+///
+/// At most `queue_capacity` records are in flight at once, counting those
+/// whose results are buffered waiting for an earlier, slower record; past
+/// that `submit` blocks until the caller `recv`s.
+pub struct WorkerPool {
+    task_tx: Option<Sender<(u64, Task)>>,
+    permit_tx: SyncSender<()>,
+    submitted: AtomicU64,
+    delivery: Mutex<Delivery>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    pub fn builder() -> WorkerPoolBuilder {
+        WorkerPoolBuilder::default()
+    }
+
+    /// Queue a task, blocking while the pool is at capacity. Another thread
+    /// must be calling `recv` for this to make progress.
+    pub fn submit(&self, task: Task) {
+        // The receiver lives in `self.delivery`, so this cannot disconnect.
+        let _ = self.permit_tx.send(());
+        self.dispatch(task);
+    }
+
+    /// Queue a task unless the pool is at capacity, in which case the task
+    /// is handed back.
+    #[allow(clippy::result_large_err)]
+    pub fn try_submit(&self, task: Task) -> Result<(), Task> {
+        match self.permit_tx.try_send(()) {
+            Ok(()) => {
+                self.dispatch(task);
+                Ok(())
+            }
+            Err(TrySendError::Full(())) | Err(TrySendError::Disconnected(())) => Err(task),
+        }
+    }
+
+    /// Next result in submission order, or `None` when nothing is in
+    /// flight.
+    pub fn recv(&self) -> Option<Outcome> {
+        let mut delivery = self.delivery.lock().unwrap();
+        if delivery.next == self.submitted.load(Ordering::SeqCst) {
+            return None;
+        }
+
+        loop {
+            let next = delivery.next;
+            if let Some(outcome) = delivery.pending.remove(&next) {
+                delivery.next += 1;
+                let _ = delivery.permits.recv();
+                return Some(outcome);
+            }
+            match delivery.results.recv() {
+                Ok((id, outcome)) => {
+                    delivery.pending.insert(id, outcome);
+                }
+                Err(_) => {
+                    delivery.pending.insert(
+                        next,
+                        Err(TransformError::Worker("all workers have exited".into())),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Run every task through the pool, calling `on_result` with each
+    /// result in task order.
+    pub fn process<I, F>(&self, tasks: I, mut on_result: F)
+    where
+        I: IntoIterator<Item = Task>,
+        F: FnMut(Outcome),
+    {
+        for task in tasks {
+            let mut task = task;
+            while let Err(rejected) = self.try_submit(task) {
+                task = rejected;
+                if let Some(outcome) = self.recv() {
+                    on_result(outcome);
+                }
+            }
+        }
+        while let Some(outcome) = self.recv() {
+            on_result(outcome);
+        }
+    }
+
+    fn dispatch(&self, task: Task) {
+        let id = self.submitted.fetch_add(1, Ordering::SeqCst);
+        // If every worker thread has exited the send fails, and `recv`
+        // reports the task as failed once the result channel disconnects.
+        if let Some(task_tx) = &self.task_tx {
+            let _ = task_tx.send((id, task));
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.task_tx.take();
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn default_program() -> io::Result<PathBuf> {
+    if let Some(program) = env::var_os(WORKER_PROGRAM_ENV) {
+        return Ok(PathBuf::from(program));
+    }
+
+    Ok(
+        env::current_exe()?.with_file_name(format!(
+            "{}{}",
+            WORKER_PROGRAM,
+            env::consts::EXE_SUFFIX
+        )),
+    )
+}
+
+fn next_task(task_rx: &Mutex<Receiver<(u64, Task)>>) -> Option<(u64, Task)> {
+    task_rx.lock().unwrap().recv().ok()
+}
+
+struct ProcessWorker {
+    program: PathBuf,
+    config: String,
+    task_timeout: Option<Duration>,
+    process: Option<WorkerProcess>,
+}
+
+impl ProcessWorker {
+    fn run(
+        mut self,
+        task_rx: Arc<Mutex<Receiver<(u64, Task)>>>,
+        result_tx: Sender<(u64, Outcome)>,
+    ) {
+        while let Some((id, task)) = next_task(&task_rx) {
+            let outcome = self.call(id, task);
+            if result_tx.send((id, outcome)).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Run one task, (re)spawning the worker process as needed. A process
+    /// that fails mid-request or runs past the task timeout is killed and
+    /// replaced on the next task.
+    fn call(&mut self, id: u64, task: Task) -> Outcome {
+        if self.process.is_none() {
+            self.process = Some(WorkerProcess::spawn(&self.program, &self.config)?);
+        }
+
+        let process = self.process.as_mut().unwrap();
+        match process.call(&Request { id, task }, self.task_timeout) {
+            Ok(response) if response.id == id => response.result,
+            Ok(response) => {
+                self.process = None;
+                Err(TransformError::Worker(format!(
+                    "expected response {} but got {}",
+                    id, response.id
+                )))
+            }
+            Err(err) => {
+                self.process = None;
+                match (err.kind(), self.task_timeout) {
+                    (io::ErrorKind::TimedOut, Some(timeout)) => {
+                        Err(TransformError::Timeout(Limit::WallClock(timeout)))
+                    }
+                    _ => Err(TransformError::Worker(err.to_string())),
+                }
+            }
+        }
+    }
+}
+
+struct WorkerProcess {
+    child: Child,
+    stdin: ChildStdin,
+    /// Lines of the process's stdout, read on a thread of their own so a
+    /// call can stop waiting for them.
+    lines: Receiver<io::Result<String>>,
+}
+
+impl WorkerProcess {
+    fn spawn(program: &PathBuf, config: &str) -> Result<Self, TransformError> {
+        let spawn_error =
+            |err: io::Error| TransformError::Worker(format!("{}: {}", program.display(), err));
+        let mut child = Command::new(program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(spawn_error)?;
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let (line_tx, lines) = mpsc::channel();
+        thread::spawn(move || read_lines(stdout, line_tx));
+        let mut process = WorkerProcess {
+            stdin: child.stdin.take().unwrap(),
+            lines,
+            child,
+        };
+
+        process
+            .stdin
+            .write_all(config.as_bytes())
+            .map_err(spawn_error)?;
+        process.stdin.write_all(b"\n").map_err(spawn_error)?;
+        process.stdin.flush().map_err(spawn_error)?;
+        let ready: Result<(), TransformError> = process.read_line(None).map_err(spawn_error)?;
+        ready?;
+
+        Ok(process)
+    }
+
+    fn call(&mut self, request: &Request, timeout: Option<Duration>) -> io::Result<Response> {
+        worker::write_line(&mut self.stdin, request)?;
+        self.read_line(timeout)
+    }
+
+    /// The next message, failing with `ErrorKind::TimedOut` if none comes
+    /// within `timeout`.
+    fn read_line<T: serde::de::DeserializeOwned>(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> io::Result<T> {
+        let line = match timeout {
+            Some(timeout) => self.lines.recv_timeout(timeout).map_err(|err| match err {
+                RecvTimeoutError::Timeout => io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("worker process did not answer within {:?}", timeout),
+                ),
+                RecvTimeoutError::Disconnected => exited(),
+            }),
+            None => self.lines.recv().map_err(|_| exited()),
+        }??;
+
+        serde_json::from_str(&line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+fn exited() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "worker process exited")
+}
+
+/// Forward the lines of `stdout` until it closes or nobody listens.
+fn read_lines(mut stdout: BufReader<ChildStdout>, lines: Sender<io::Result<String>>) {
+    loop {
+        let mut line = String::new();
+        let line = match stdout.read_line(&mut line) {
+            Ok(0) => Err(exited()),
+            Ok(_) => Ok(line),
+            Err(err) => Err(err),
+        };
+        let done = line.is_err();
+        if lines.send(line).is_err() || done {
+            return;
+        }
+    }
+}
+
+impl Drop for WorkerProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,263 @@
+//! Record-scoped transforms: methods that take the whole record rather
+//! than one field value.
+//!
+//! Each declares the record paths it reads, so a spec can be checked
+//! before it runs and every record checked before the method is called.
+//! The method is then handed only those paths, not the full record.
+
+use serde_json::{Map, Value};
+
+use crate::{error::TransformError, spec};
+
+/// A record path read by a record-scoped method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Input {
+    /// Dotted path, or `{param}` for the path named by a string param.
+    pub path: &'static str,
+    /// Whether the method fails without it.
+    pub required: bool,
+    /// Whether `path` is a top-level key read with `record.get(key)`, dots
+    /// and all, rather than a dotted path.
+    pub key: bool,
+}
+
+/// A FieldTransformer method that takes the whole record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordMethod {
+    pub name: &'static str,
+    pub inputs: &'static [Input],
+    /// Params the method needs to know what to read.
+    pub params: &'static [&'static str],
+}
+
+const fn required(path: &'static str) -> Input {
+    Input {
+        path,
+        required: true,
+        key: false,
+    }
+}
+
+const fn optional(path: &'static str) -> Input {
+    Input {
+        path,
+        required: false,
+        key: false,
+    }
+}
+
+const fn optional_key(path: &'static str) -> Input {
+    Input {
+        path,
+        required: false,
+        key: true,
+    }
+}
+
+const METHODS: &[RecordMethod] = &[
+    // `record.get(params["start"], 0)`: missing timestamps count as 0, and
+    // `start` and `end` name top-level keys, not paths.
+    RecordMethod {
+        name: "calculate_duration",
+        inputs: &[optional_key("{start}"), optional_key("{end}")],
+        params: &["start", "end"],
+    },
+    // Which `data` fields are read depends on the primary code.
+    RecordMethod {
+        name: "extract_software_reset_cause_infomation",
+        inputs: &[
+            required("parsed_hardware_log.primary_code"),
+            optional("parsed_hardware_log.secondary_code"),
+            optional("parsed_hardware_log.data"),
+        ],
+        params: &[],
+    },
+    RecordMethod {
+        name: "extract_app_log_error_code",
+        inputs: &[required("platform"), optional_key("{code_field}")],
+        params: &["code_field"],
+    },
+];
+
+/// The record-scoped method `name`, if it is one.
+pub fn lookup(name: &str) -> Option<&'static RecordMethod> {
+    METHODS.iter().find(|method| method.name == name)
+}
+
+/// A record path with its params substituted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputPath {
+    pub path: String,
+    pub required: bool,
+    /// See `Input::key`.
+    pub key: bool,
+}
+
+impl RecordMethod {
+    /// The paths this method reads with `params`.
+    pub fn input_paths(&self, params: &Map<String, Value>) -> Result<Vec<InputPath>, String> {
+        if let Some(param) = self
+            .params
+            .iter()
+            .find(|param| !params.contains_key(**param))
+        {
+            return Err(format!("{} needs param '{}'", self.name, param));
+        }
+
+        self.inputs
+            .iter()
+            .map(|input| {
+                let path = match input
+                    .path
+                    .strip_prefix('{')
+                    .and_then(|p| p.strip_suffix('}'))
+                {
+                    Some(param) => params
+                        .get(param)
+                        .and_then(Value::as_str)
+                        .ok_or_else(|| {
+                            format!("{} param '{}' must be a path string", self.name, param)
+                        })?
+                        .to_owned(),
+                    None => input.path.to_owned(),
+                };
+                Ok(InputPath {
+                    path,
+                    required: input.required,
+                    key: input.key,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The part of `record` under `inputs`, failing on the first required
+/// path it lacks.
+pub fn project(record: &Value, inputs: &[InputPath]) -> Result<Value, TransformError> {
+    let mut projection = Value::Object(Map::new());
+    for input in inputs {
+        let found = if input.key {
+            copy_key(record, &input.path, &mut projection)
+        } else {
+            copy_path(record, &input.path, &mut projection)
+        };
+        if !found && input.required {
+            return Err(TransformError::MissingInput(input.path.clone()));
+        }
+    }
+    Ok(projection)
+}
+
+/// Copy the top-level `key` of `record` to `projection`.
+fn copy_key(record: &Value, key: &str, projection: &mut Value) -> bool {
+    let (Value::Object(fields), Value::Object(copy)) = (record, projection) else {
+        return false;
+    };
+    match fields.get(key) {
+        Some(value) => {
+            copy.insert(key.to_owned(), value.clone());
+            true
+        }
+        None => false,
+    }
+}
+
+/// Copy the value at `path` in `record` to the same path in `projection`,
+/// creating objects on the way. Arrays are copied whole.
+fn copy_path(record: &Value, path: &str, projection: &mut Value) -> bool {
+    if path == spec::WHOLE_RECORD {
+        *projection = record.clone();
+        return true;
+    }
+
+    let mut source = record;
+    let mut target = projection;
+    let mut segments = path.split('.').peekable();
+    while let Some(segment) = segments.next() {
+        let (Value::Object(fields), Value::Object(copy)) = (source, target) else {
+            return false;
+        };
+        let Some(value) = fields.get(segment) else {
+            return false;
+        };
+
+        if segments.peek().is_none() || !value.is_object() {
+            let rest: Vec<_> = segments.collect();
+            let found = rest.is_empty() || spec::resolve(value, &rest.join(".")).is_some();
+            if found {
+                copy.insert(segment.to_owned(), value.clone());
+            }
+            return found;
+        }
+
+        source = value;
+        target = copy
+            .entry(segment)
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn inputs(method: &str, params: Value) -> Vec<InputPath> {
+        let Value::Object(params) = params else {
+            unreachable!()
+        };
+        lookup(method).unwrap().input_paths(&params).unwrap()
+    }
+
+    #[test]
+    fn paths_are_projected_with_their_parents() {
+        let record = json!({
+            "parsed_hardware_log": {"primary_code": 1, "other": 2},
+            "serial": "X",
+        });
+        assert_eq!(
+            project(
+                &record,
+                &inputs("extract_software_reset_cause_infomation", json!({}))
+            ),
+            Ok(json!({"parsed_hardware_log": {"primary_code": 1}}))
+        );
+    }
+
+    #[test]
+    fn missing_required_paths_fail() {
+        assert_eq!(
+            project(
+                &json!({"serial": "X"}),
+                &inputs("extract_software_reset_cause_infomation", json!({}))
+            ),
+            Err(TransformError::MissingInput(
+                "parsed_hardware_log.primary_code".into()
+            ))
+        );
+    }
+
+    // Python reads `record.get(params["start"])`, so a dotted name is one key.
+    #[test]
+    fn calculate_duration_reads_flat_keys() {
+        let inputs = inputs(
+            "calculate_duration",
+            json!({"start": "session.start", "end": "end"}),
+        );
+        let record = json!({
+            "session.start": 10,
+            "session": {"start": 99},
+            "end": 25,
+        });
+        assert_eq!(
+            project(&record, &inputs),
+            Ok(json!({"session.start": 10, "end": 25}))
+        );
+        assert_eq!(
+            project(&json!({"session": {"start": 99}}), &inputs),
+            Ok(json!({}))
+        );
+    }
+}
@@ -0,0 +1,731 @@
+//! `ScriptBackend` on RustPython, for builds without libpython.
+//!
+//! RustPython runs the same FieldTransformer module as `Engine` and follows
+//! the same value mapping (see `convert`), with these differences:
+//!
+//! * values cross the boundary as JSON text, so integers beyond the
+//!   i64/u64 range come back as floats instead of a conversion error;
+//! * RustPython resolves builtins and `__import__` through the interpreter
+//!   rather than the snippet's `__builtins__`, so the `SandboxPolicy` is
+//!   enforced by a static check of the snippet instead: importing a module
+//!   or using a builtin outside the policy is rejected before it runs;
+//! * `Limits` are not enforced;
+//! * when `python-dateutil` is not importable, a `dateutil.tz.tzoffset`
+//!   built on `datetime.timezone` stands in for it.
+
+use std::cell::RefCell;
+
+use rustpython_vm::{
+    builtins::{PyBaseException, PyBaseExceptionRef, PyTuple, PyTupleRef},
+    function::PosArgs,
+    import, AsObject, Interpreter, PyObjectRef, PyResult, VirtualMachine,
+};
+use serde_json::{Map, Value};
+
+use crate::{
+    backend::{BackendKind, ScriptBackend, Snippet},
+    cache::{self, CodeCache},
+    capture::{self, log_output, Captured, CAPTURE_CODE},
+    error::TransformError,
+    loader::{load_error, FieldTransformerSource, MODULE_NAME},
+    native::{self, NativeFn},
+    sandbox::{SandboxPolicy, SANDBOX_CODE},
+};
+
+const HOST_CODE: &str = r#"
+import ast
+import builtins
+import json
+import sys
+import traceback
+import types
+from datetime import date, datetime, time, timedelta, timezone
+
+from _capture import CappedWriter
+from _sandbox import SandboxViolation, check, harden
+
+MISSING = object()
+
+
+def install_dateutil():
+    try:
+        import dateutil.tz
+        return
+    except ImportError:
+        pass
+
+    def tzoffset(name, offset):
+        if not isinstance(offset, timedelta):
+            offset = timedelta(seconds=offset)
+        return timezone(offset, name) if name else timezone(offset)
+
+    package = types.ModuleType("dateutil")
+    tz = types.ModuleType("dateutil.tz")
+    tz.tzoffset = tzoffset
+    package.tz = tz
+    sys.modules["dateutil"] = package
+    sys.modules["dateutil.tz"] = tz
+
+
+def load(code, filename, name):
+    install_dateutil()
+    module = types.ModuleType(name)
+    module.__file__ = filename
+    sys.modules[name] = module
+    exec(compile(code, filename, "exec"), module.__dict__)
+
+    cls = module.FieldTransformer
+    namespace = dict(cls.EXEC_SAFE_DICT)
+    namespace["field_transformer"] = cls()
+    namespace["FieldTransformer"] = cls
+    if hasattr(module, "TransformationException"):
+        namespace["TransformationException"] = module.TransformationException
+    return namespace
+
+
+class Policy:
+    def __init__(self, policy):
+        policy = json.loads(policy)
+        self.builtins = frozenset(policy["builtins"])
+        self.allowed_modules = frozenset(policy["allowed_modules"])
+
+    def apply(self, namespace):
+        namespace.pop("FieldTransformer", None)
+        if "field_transformer" in namespace:
+            harden(namespace["field_transformer"])
+
+    def check(self, source, filename, namespace):
+        check(source, filename)
+        tree = ast.parse(source, filename)
+        bound = set(namespace)
+        for node in ast.walk(tree):
+            if isinstance(node, ast.Name) and not isinstance(node.ctx, ast.Load):
+                bound.add(node.id)
+            elif isinstance(node, (ast.FunctionDef, ast.ClassDef)):
+                bound.add(node.name)
+            elif isinstance(node, ast.arg):
+                bound.add(node.arg)
+            elif isinstance(node, ast.alias):
+                bound.add((node.asname or node.name).partition(".")[0])
+
+        for node in ast.walk(tree):
+            if isinstance(node, ast.Import):
+                for alias in node.names:
+                    self.check_import(alias.name, 0)
+            elif isinstance(node, ast.ImportFrom):
+                self.check_import(node.module or "", node.level)
+            elif (isinstance(node, ast.Name) and isinstance(node.ctx, ast.Load)
+                    and node.id not in bound and node.id not in self.builtins
+                    and hasattr(builtins, node.id)):
+                raise SandboxViolation(
+                    f"use of builtin '{node.id}' is not allowed (line {node.lineno})")
+
+    def check_import(self, name, level):
+        if level != 0:
+            raise SandboxViolation(
+                f"relative import of '{'.' * level}{name}' is not allowed", name=name)
+        if name.partition(".")[0] not in self.allowed_modules:
+            raise SandboxViolation(f"import of module '{name}' is not allowed", name=name)
+
+
+def compile_snippet(source, filename, namespace, policy):
+    if policy is not None:
+        policy.check(source, filename, namespace)
+    return compile(source, filename, "exec")
+
+
+def run_snippet(code, namespace, value):
+    globals = dict(namespace)
+    globals["value"] = json.loads(value)
+    exec(code, globals)
+    return globals.get("output", MISSING)
+
+
+def run_method(namespace, value, method_name, params):
+    field_transformer = namespace["field_transformer"]
+    return field_transformer.transform(json.loads(value), method_name, **json.loads(params))
+
+
+def captured(limit, function, *args):
+    stdout, stderr = CappedWriter(limit), CappedWriter(limit)
+    host_stdout, host_stderr = sys.stdout, sys.stderr
+    sys.stdout, sys.stderr = stdout, stderr
+    try:
+        ok, result = True, function(*args)
+    except BaseException as exc:
+        ok, result = False, exc
+    finally:
+        sys.stdout, sys.stderr = host_stdout, host_stderr
+    truncated = stdout.truncated or stderr.truncated
+    return ok, result, stdout.getvalue(), stderr.getvalue(), truncated
+
+
+def _default(obj):
+    if isinstance(obj, (set, frozenset)):
+        return list(obj)
+    if isinstance(obj, (datetime, date, time)):
+        return obj.isoformat()
+    if isinstance(obj, timedelta):
+        return obj.total_seconds()
+    raise TypeError(f"cannot convert Python {type(obj).__name__} to JSON")
+
+
+def encode(obj):
+    if obj is MISSING:
+        return None
+    return json.dumps(obj, default=_default, allow_nan=False)
+
+
+def describe(exc):
+    if isinstance(exc, SyntaxError):
+        return "syntax", "SyntaxError", exc.msg or str(exc), exc.lineno, exc.offset, ""
+    if isinstance(exc, SandboxViolation):
+        return "sandbox", "SandboxViolation", str(exc), None, None, ""
+    if any(cls.__name__ == "TransformationException" for cls in type(exc).__mro__):
+        return "transformation", type(exc).__name__, str(exc), None, None, ""
+    tb = exc.__traceback__
+    while tb is not None and tb.tb_frame.f_code.co_filename == "<source>":
+        tb = tb.tb_next
+    lines = traceback.format_exception(type(exc), exc, tb)
+    return "python", type(exc).__name__, str(exc), None, None, "".join(lines)
+"#;
+
+/// RustPython interpreter with the FieldTransformer module loaded.
+///
+/// Same contract as `Engine`: snippets run in a fresh copy of the module
+/// namespace, compiled snippets are cached by source, and output is
+/// captured per call. The interpreter is single-threaded, so the engine is
+/// neither `Send` nor `Sync`.
+pub struct RustPythonEngine {
+    interpreter: Interpreter,
+    host: PyObjectRef,
+    namespace: PyObjectRef,
+    policy: Option<PyObjectRef>,
+    code_cache: RefCell<CodeCache<PyObjectRef>>,
+    output_limit: usize,
+    native_transforms: bool,
+}
+
+pub struct RustPythonEngineBuilder {
+    source: FieldTransformerSource,
+    cache_capacity: usize,
+    sandbox: Option<SandboxPolicy>,
+    output_limit: usize,
+    native_transforms: bool,
+}
+
+impl Default for RustPythonEngineBuilder {
+    fn default() -> Self {
+        RustPythonEngineBuilder {
+            source: FieldTransformerSource::default(),
+            cache_capacity: cache::DEFAULT_CAPACITY,
+            sandbox: Some(SandboxPolicy::default()),
+            output_limit: capture::DEFAULT_OUTPUT_LIMIT,
+            native_transforms: true,
+        }
+    }
+}
+
+impl RustPythonEngineBuilder {
+    pub fn source(mut self, source: FieldTransformerSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Maximum number of compiled snippets kept around; 0 disables caching.
+    pub fn cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache_capacity = capacity;
+        self
+    }
+
+    pub fn sandbox(mut self, policy: SandboxPolicy) -> Self {
+        self.sandbox = Some(policy);
+        self
+    }
+
+    pub fn without_sandbox(mut self) -> Self {
+        self.sandbox = None;
+        self
+    }
+
+    /// Characters of stdout and of stderr kept per call; the rest is
+    /// dropped and the output marked truncated.
+    pub fn output_limit(mut self, limit: usize) -> Self {
+        self.output_limit = limit;
+        self
+    }
+
+    /// Answer `transform` calls for methods ported to `native` without
+    /// entering Python; on by default. Turn it off when the loaded module
+    /// changes what those methods do.
+    pub fn native_transforms(mut self, enabled: bool) -> Self {
+        self.native_transforms = enabled;
+        self
+    }
+
+    pub fn build(self) -> Result<RustPythonEngine, TransformError> {
+        let (code, file_name) = self.source.read()?;
+        let interpreter = Interpreter::with_init(Default::default(), |vm| {
+            vm.add_native_modules(rustpython_stdlib::get_module_inits());
+            vm.add_frozen(rustpython_pylib::FROZEN_STDLIB);
+        });
+
+        let (host, policy, namespace) = interpreter.enter(|vm| {
+            let host = import::import_source(vm, "_capture", CAPTURE_CODE)
+                .and_then(|_| import::import_source(vm, "_sandbox", SANDBOX_CODE))
+                .and_then(|_| import::import_source(vm, "_host", HOST_CODE))
+                .map_err(|exc| TransformError::Load(exception_message(vm, &exc)))?;
+
+            let policy = || -> PyResult<Option<PyObjectRef>> {
+                match &self.sandbox {
+                    Some(policy) => {
+                        let policy = serde_json::to_string(policy)
+                            .map_err(|err| vm.new_value_error(err.to_string()))?;
+                        Ok(Some(host.get_attr("Policy", vm)?.call((policy,), vm)?))
+                    }
+                    None => Ok(None),
+                }
+            };
+
+            let policy = policy().map_err(|exc| error(vm, &host, exc))?;
+            let namespace = load_namespace(vm, &host, policy.as_ref(), code, &file_name)?;
+            Ok::<_, TransformError>((host, policy, namespace))
+        })?;
+
+        Ok(RustPythonEngine {
+            interpreter,
+            host,
+            namespace,
+            policy,
+            code_cache: RefCell::new(CodeCache::new(self.cache_capacity)),
+            output_limit: self.output_limit,
+            native_transforms: self.native_transforms,
+        })
+    }
+}
+
+impl RustPythonEngine {
+    pub fn new() -> Result<Self, TransformError> {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> RustPythonEngineBuilder {
+        RustPythonEngineBuilder::default()
+    }
+
+    /// Number of compiled snippets currently cached.
+    pub fn cached_snippets(&self) -> usize {
+        self.code_cache.borrow().len()
+    }
+
+    fn native_method(&self, method_name: &str) -> Option<NativeFn> {
+        self.native_transforms
+            .then(|| native::lookup(method_name))
+            .flatten()
+    }
+
+    /// Like `ScriptBackend::transform`, returning the captured
+    /// stdout/stderr alongside.
+    pub fn run_transform(
+        &self,
+        value: &Value,
+        method_name: &str,
+        params: &Map<String, Value>,
+    ) -> Captured<Result<Value, TransformError>> {
+        if let Some(native) = self.native_method(method_name) {
+            return Captured::silent(native(value, params));
+        }
+
+        self.interpreter.enter(|vm| {
+            let args = vec![
+                self.namespace.clone(),
+                vm.ctx.new_str(value.to_string()).into(),
+                vm.ctx.new_str(method_name).into(),
+                vm.ctx
+                    .new_str(Value::Object(params.clone()).to_string())
+                    .into(),
+            ];
+            self.captured(vm, "run_method", args)
+        })
+    }
+
+    /// Like `ScriptBackend::transform_with_custom_code`, returning the
+    /// captured stdout/stderr alongside.
+    pub fn run_custom_code(
+        &self,
+        value: &Value,
+        custom_code: &str,
+    ) -> Captured<Result<Value, TransformError>> {
+        self.interpreter.enter(|vm| {
+            let code = match self.compile_code(vm, custom_code) {
+                Ok(code) => code,
+                Err(err) => return Captured::silent(Err(err)),
+            };
+            let args = vec![
+                code,
+                self.namespace.clone(),
+                vm.ctx.new_str(value.to_string()).into(),
+            ];
+            self.captured(vm, "run_snippet", args)
+        })
+    }
+
+    fn compile_code(
+        &self,
+        vm: &VirtualMachine,
+        custom_code: &str,
+    ) -> Result<PyObjectRef, TransformError> {
+        let mut code_cache = self.code_cache.borrow_mut();
+
+        code_cache.get_or_compile(custom_code, |key| {
+            let args = vec![
+                vm.ctx.new_str(custom_code).into(),
+                vm.ctx.new_str(format!("<custom_code {:016x}>", key)).into(),
+                self.namespace.clone(),
+                self.policy.clone().unwrap_or_else(|| vm.ctx.none()),
+            ];
+
+            self.host
+                .get_attr("compile_snippet", vm)
+                .and_then(|compile| compile.call(PosArgs::new(args), vm))
+                .map_err(|exc| error(vm, &self.host, exc))
+        })
+    }
+
+    /// Run `host.<function>(*args)` with stdout/stderr captured and convert
+    /// its result.
+    fn captured(
+        &self,
+        vm: &VirtualMachine,
+        function: &'static str,
+        args: Vec<PyObjectRef>,
+    ) -> Captured<Result<Value, TransformError>> {
+        let run = || -> PyResult<Captured<Result<Value, TransformError>>> {
+            let mut captured_args = vec![
+                vm.ctx.new_int(self.output_limit).into(),
+                self.host.get_attr(function, vm)?,
+            ];
+            captured_args.extend(args);
+
+            let outcome = self
+                .host
+                .get_attr("captured", vm)?
+                .call(PosArgs::new(captured_args), vm)?;
+            let outcome = tuple(vm, outcome)?;
+            let [ok, result, stdout, stderr, truncated] = outcome.as_slice() else {
+                return Err(vm.new_type_error("captured() must return 5 items".to_owned()));
+            };
+
+            let result = if ok.clone().try_to_bool(vm)? {
+                self.decode(vm, result.clone())
+            } else {
+                let exc = result
+                    .clone()
+                    .downcast::<PyBaseException>()
+                    .map_err(|_| vm.new_type_error("expected an exception".to_owned()))?;
+                Err(error(vm, &self.host, exc))
+            };
+
+            Ok(Captured {
+                result,
+                stdout: text(vm, stdout)?,
+                stderr: text(vm, stderr)?,
+                truncated: truncated.clone().try_to_bool(vm)?,
+            })
+        };
+
+        run().unwrap_or_else(|exc| Captured::silent(Err(error(vm, &self.host, exc))))
+    }
+
+    /// Convert a Python result to JSON through `host.encode`.
+    fn decode(&self, vm: &VirtualMachine, output: PyObjectRef) -> Result<Value, TransformError> {
+        let encoded = self
+            .host
+            .get_attr("encode", vm)
+            .and_then(|encode| encode.call((output,), vm))
+            .map_err(|exc| TransformError::Conversion(exception_message(vm, &exc)))?;
+        if vm.is_none(&encoded) {
+            return Err(TransformError::MissingOutput);
+        }
+
+        let encoded = text(vm, &encoded)
+            .map_err(|exc| TransformError::Conversion(exception_message(vm, &exc)))?;
+        serde_json::from_str(&encoded).map_err(|err| TransformError::Conversion(err.to_string()))
+    }
+}
+
+impl ScriptBackend for RustPythonEngine {
+    fn kind(&self) -> BackendKind {
+        BackendKind::RustPython
+    }
+
+    fn load_module(&mut self, source: &FieldTransformerSource) -> Result<(), TransformError> {
+        let (code, file_name) = source.read()?;
+        self.namespace = self
+            .interpreter
+            .enter(|vm| load_namespace(vm, &self.host, self.policy.as_ref(), code, &file_name))?;
+        // The sandbox check of cached snippets depends on the namespace.
+        self.code_cache.borrow_mut().clear();
+        Ok(())
+    }
+
+    fn compile(&self, custom_code: &str) -> Result<Snippet, TransformError> {
+        self.interpreter
+            .enter(|vm| self.compile_code(vm, custom_code))?;
+        Ok(Snippet::new(custom_code))
+    }
+
+    fn call(&self, snippet: &Snippet, value: &Value) -> Result<Value, TransformError> {
+        log_output(self.run_custom_code(value, snippet.source()))
+    }
+
+    fn transform(
+        &self,
+        value: &Value,
+        method_name: &str,
+        params: &Map<String, Value>,
+    ) -> Result<Value, TransformError> {
+        log_output(self.run_transform(value, method_name, params))
+    }
+}
+
+fn load_namespace(
+    vm: &VirtualMachine,
+    host: &PyObjectRef,
+    policy: Option<&PyObjectRef>,
+    code: String,
+    file_name: &str,
+) -> Result<PyObjectRef, TransformError> {
+    let load = || -> PyResult {
+        let namespace = host
+            .get_attr("load", vm)?
+            .call((code, file_name.to_owned(), MODULE_NAME.to_owned()), vm)?;
+        if let Some(policy) = policy {
+            policy
+                .get_attr("apply", vm)?
+                .call((namespace.clone(),), vm)?;
+        }
+        Ok(namespace)
+    };
+
+    load().map_err(|exc| load_error(file_name, error(vm, host, exc)))
+}
+
+/// Map a RustPython exception through `host.describe`, mirroring
+/// `TransformError::from_py`.
+fn error(vm: &VirtualMachine, host: &PyObjectRef, exc: PyBaseExceptionRef) -> TransformError {
+    let describe = || -> PyResult<TransformError> {
+        let description = host.get_attr("describe", vm)?.call((exc.clone(),), vm)?;
+        let description = tuple(vm, description)?;
+        let [kind, type_name, message, line, column, traceback] = description.as_slice() else {
+            return Err(vm.new_type_error("describe() must return 6 items".to_owned()));
+        };
+        let number = |obj: &PyObjectRef| -> PyResult<Option<usize>> {
+            if vm.is_none(obj) {
+                return Ok(None);
+            }
+            Ok(text(vm, obj)?.parse().ok())
+        };
+
+        let message = text(vm, message)?;
+        Ok(match text(vm, kind)?.as_str() {
+            "syntax" => TransformError::Syntax {
+                message,
+                line: number(line)?,
+                column: number(column)?,
+            },
+            "sandbox" => TransformError::Sandbox(message),
+            "transformation" => TransformError::Transformation(message),
+            _ => TransformError::Python {
+                type_name: text(vm, type_name)?,
+                message,
+                traceback: text(vm, traceback)?,
+            },
+        })
+    };
+
+    describe().unwrap_or_else(|_| TransformError::Python {
+        type_name: exc.class().name().to_string(),
+        message: exception_message(vm, &exc),
+        traceback: String::new(),
+    })
+}
+
+fn tuple(vm: &VirtualMachine, obj: PyObjectRef) -> PyResult<PyTupleRef> {
+    obj.downcast::<PyTuple>()
+        .map_err(|_| vm.new_type_error("expected a tuple".to_owned()))
+}
+
+fn text(vm: &VirtualMachine, obj: &PyObjectRef) -> PyResult<String> {
+    Ok(obj.str(vm)?.as_str().to_owned())
+}
+
+fn exception_message(vm: &VirtualMachine, exc: &PyBaseExceptionRef) -> String {
+    exc.as_object()
+        .str(vm)
+        .map(|s| s.as_str().to_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Snippets and methods both backends must agree on, with the result
+    /// they give.
+    fn shared_snippets() -> Vec<(Value, &'static str, Value)> {
+        vec![
+            (json!(3), "output = value * 2", json!(6)),
+            (
+                json!({"a": [1, 2.5, null, true]}),
+                "output = {'b': value['a'], 'n': len(value['a'])}",
+                json!({"b": [1, 2.5, null, true], "n": 4}),
+            ),
+            (
+                json!("DW.MM.1-Flex.1.ZH"),
+                "output = field_transformer.extract_ate_id(value)[2]",
+                json!("FLEX"),
+            ),
+            (
+                json!("2018-01-01"),
+                "import datetime\noutput = datetime.date.fromisoformat(value).isoformat()",
+                json!("2018-01-01"),
+            ),
+        ]
+    }
+
+    fn shared_methods() -> Vec<(Value, &'static str, Value)> {
+        vec![
+            (json!("1.0.12-debug"), "normalize_version", json!("1.0.12")),
+            (
+                json!({"$date": "2018-01-01T02:02:02.123Z"}),
+                "to_date_index",
+                json!(20180101),
+            ),
+            (
+                json!("W0DFA12345"),
+                "serial_number_to_owner",
+                json!("fossil"),
+            ),
+        ]
+    }
+
+    fn python_only() -> RustPythonEngine {
+        RustPythonEngine::builder()
+            .native_transforms(false)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn snippets_give_the_expected_results() {
+        let engine = RustPythonEngine::new().unwrap();
+        for (value, code, expected) in shared_snippets() {
+            assert_eq!(
+                engine.transform_with_custom_code(&value, code),
+                Ok(expected),
+                "{}",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn methods_give_the_expected_results() {
+        let engine = python_only();
+        for (value, method, expected) in shared_methods() {
+            assert_eq!(
+                engine.transform(&value, method, &Map::new()),
+                Ok(expected),
+                "{}",
+                method
+            );
+        }
+    }
+
+    #[test]
+    fn sandbox_denies_imports_and_dunders() {
+        let engine = RustPythonEngine::new().unwrap();
+        let denied = |code: &str| match engine.transform_with_custom_code(&json!(1), code) {
+            Err(TransformError::Sandbox(message)) => message,
+            other => panic!("{:?} was not denied: {:?}", code, other),
+        };
+
+        assert!(denied("import os\noutput = 1").contains("'os'"));
+        assert!(denied("output = __import__('os')").contains("'__import__'"));
+        assert!(denied("output = value.__class__").contains("'__class__'"));
+        assert!(denied("output = open('/etc/passwd')").contains("'open'"));
+    }
+
+    #[test]
+    fn policy_extends_the_allow_list() {
+        let engine = RustPythonEngine::builder()
+            .sandbox(SandboxPolicy::default().allow_module("string"))
+            .build()
+            .unwrap();
+        assert_eq!(
+            engine.transform_with_custom_code(&json!(1), "import string\noutput = string.digits"),
+            Ok(json!("0123456789"))
+        );
+    }
+
+    #[cfg(feature = "cpython")]
+    #[test]
+    fn matches_the_pyo3_backend() {
+        let rustpython = python_only();
+        let pyo3 = crate::engine::Engine::builder()
+            .native_transforms(false)
+            .build()
+            .unwrap();
+
+        for (value, code, _) in shared_snippets() {
+            assert_eq!(
+                rustpython.transform_with_custom_code(&value, code),
+                pyo3.transform_with_custom_code(&value, code),
+                "{}",
+                code
+            );
+        }
+        for (value, method, _) in shared_methods() {
+            assert_eq!(
+                rustpython.transform(&value, method, &Map::new()),
+                ScriptBackend::transform(&pyo3, &value, method, &Map::new()),
+                "{}",
+                method
+            );
+        }
+        for code in ["import os\noutput = 1", "output = value.__class__"] {
+            let category =
+                |result: Result<Value, TransformError>| result.map_err(|err| err.category());
+            assert_eq!(
+                category(rustpython.transform_with_custom_code(&json!(1), code)),
+                category(pyo3.transform_with_custom_code(&json!(1), code)),
+                "{}",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn broken_modules_fail_to_load() {
+        let source = FieldTransformerSource::Code("class FieldTransformer(:\n".into());
+        match RustPythonEngine::builder().source(source.clone()).build() {
+            Err(TransformError::Load(message)) => {
+                assert!(message.starts_with("<field_transformer>: "), "{}", message)
+            }
+            other => panic!("expected a load error, got {:?}", other.err()),
+        }
+
+        let mut engine = RustPythonEngine::new().unwrap();
+        assert!(matches!(
+            engine.load_module(&source),
+            Err(TransformError::Load(_))
+        ));
+        assert_eq!(
+            engine.transform(&json!("1.0.12-debug"), "normalize_version", &Map::new()),
+            Ok(json!("1.0.12"))
+        );
+    }
+}
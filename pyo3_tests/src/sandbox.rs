@@ -0,0 +1,306 @@
+#[cfg(feature = "cpython")]
+use pyo3::{
+    prelude::*,
+    types::{PyDict, PyModule},
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "cpython")]
+use crate::error::TransformError;
+
+/// Modules custom code may import by default, matching
+/// `FieldTransformer.EXEC_SAFE_LIST`.
+pub const DEFAULT_ALLOWED_MODULES: &[&str] = &["math", "datetime", "json", "re"];
+
+/// Builtins custom code can see by default. Anything that reaches the file
+/// system, the interpreter internals or arbitrary attributes (`open`,
+/// `eval`, `exec`, `compile`, `getattr`, `globals`, `type`, ...) is left
+/// out; `__import__` is always replaced by the allow-list hook.
+#[rustfmt::skip]
+pub const DEFAULT_BUILTINS: &[&str] = &[
+    "abs", "all", "any", "ascii", "bin", "bool", "bytearray", "bytes", "callable", "chr",
+    "complex", "dict", "divmod", "enumerate", "filter", "float", "format", "frozenset", "hash",
+    "hex", "int", "isinstance", "issubclass", "iter", "len", "list", "map", "max", "min", "next",
+    "oct", "ord", "pow", "print", "range", "repr", "reversed", "round", "set", "slice", "sorted",
+    "str", "sum", "tuple", "zip", "__build_class__",
+    "ArithmeticError", "AssertionError", "AttributeError", "Exception", "ImportError",
+    "IndexError", "KeyError", "LookupError", "NameError", "NotImplementedError",
+    "OverflowError", "RuntimeError", "StopIteration", "TypeError", "UnicodeDecodeError",
+    "UnicodeEncodeError", "UnicodeError", "ValueError", "ZeroDivisionError",
+];
+
+pub(crate) const SANDBOX_CODE: &str = r#"
+import ast
+import builtins
+
+
+class SandboxViolation(ImportError):
+    pass
+
+
+def make_builtins(names, allowed_modules):
+    allowed_modules = frozenset(allowed_modules)
+    real_import = builtins.__import__
+
+    def guarded_import(name, globals=None, locals=None, fromlist=(), level=0):
+        if level != 0:
+            raise SandboxViolation(
+                f"relative import of '{'.' * level}{name}' is not allowed", name=name)
+        if name.partition(".")[0] not in allowed_modules:
+            raise SandboxViolation(f"import of module '{name}' is not allowed", name=name)
+        return real_import(name, globals, locals, fromlist, level)
+
+    table = {name: getattr(builtins, name) for name in names if hasattr(builtins, name)}
+    table["__import__"] = guarded_import
+    return table
+
+
+def check(source, filename):
+    for node in ast.walk(ast.parse(source, filename)):
+        if isinstance(node, ast.Attribute) and node.attr.startswith("_"):
+            raise SandboxViolation(
+                f"access to attribute '{node.attr}' is not allowed (line {node.lineno})")
+        if isinstance(node, ast.Name) and node.id.startswith("__"):
+            raise SandboxViolation(
+                f"access to name '{node.id}' is not allowed (line {node.lineno})")
+
+
+def harden(field_transformer):
+    dispatch = field_transformer.transform
+
+    def transform(value, method_name, **params):
+        if method_name.startswith("_") or method_name == "transform_with_custom_code":
+            raise SandboxViolation(f"method '{method_name}' is not allowed")
+        return dispatch(value, method_name, **params)
+
+    def transform_with_custom_code(*args, **kwargs):
+        raise SandboxViolation("nested custom code is not allowed")
+
+    field_transformer.transform = transform
+    field_transformer.transform_with_custom_code = transform_with_custom_code
+"#;
+
+/// What custom code may touch.
+///
+/// The policy blocks accidental and casual escapes (`import os`, `open()`,
+/// dunder walks such as `().__class__.__bases__`); it is not a hard security
+/// boundary against a determined attacker, for which the process-level
+/// isolation of a worker pool is the better tool.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SandboxPolicy {
+    /// Top-level module names `import` may resolve; submodules of an
+    /// allowed package are allowed too.
+    pub allowed_modules: Vec<String>,
+    /// Names copied from `builtins` into the snippet's `__builtins__`.
+    pub builtins: Vec<String>,
+}
+
+impl Default for SandboxPolicy {
+    fn default() -> Self {
+        SandboxPolicy {
+            allowed_modules: DEFAULT_ALLOWED_MODULES
+                .iter()
+                .map(|m| m.to_string())
+                .collect(),
+            builtins: DEFAULT_BUILTINS.iter().map(|b| b.to_string()).collect(),
+        }
+    }
+}
+
+impl SandboxPolicy {
+    pub fn allow_module(mut self, module: &str) -> Self {
+        self.allowed_modules.push(module.to_owned());
+        self
+    }
+}
+
+/// A `SandboxPolicy` instantiated in a running interpreter.
+#[cfg(feature = "cpython")]
+pub(crate) struct Sandbox {
+    builtins: Py<PyDict>,
+    check: PyObject,
+    harden: PyObject,
+    violation: PyObject,
+}
+
+#[cfg(feature = "cpython")]
+impl Sandbox {
+    pub fn new(py: Python, policy: &SandboxPolicy) -> Result<Self, TransformError> {
+        let build = || -> PyResult<Sandbox> {
+            let module = PyModule::from_code(py, SANDBOX_CODE, "<sandbox>", "_sandbox")?;
+            let builtins = module
+                .getattr("make_builtins")?
+                .call1((policy.builtins.clone(), policy.allowed_modules.clone()))?
+                .downcast::<PyDict>()?;
+
+            Ok(Sandbox {
+                builtins: builtins.into(),
+                check: module.getattr("check")?.into(),
+                harden: module.getattr("harden")?.into(),
+                violation: module.getattr("SandboxViolation")?.into(),
+            })
+        };
+
+        build().map_err(|err| TransformError::from_py(py, err))
+    }
+
+    /// Restrict the namespace custom code runs in.
+    pub fn apply(&self, py: Python, namespace: &PyDict) -> Result<(), TransformError> {
+        let apply = || -> PyResult<()> {
+            namespace.set_item("__builtins__", self.builtins.as_ref(py))?;
+            if namespace.contains("FieldTransformer")? {
+                namespace.del_item("FieldTransformer")?;
+            }
+            if let Some(field_transformer) = namespace.get_item("field_transformer") {
+                self.harden.call1(py, (field_transformer,))?;
+            }
+            Ok(())
+        };
+
+        apply().map_err(|err| self.error(py, err))
+    }
+
+    /// Reject snippets that reach for private or dunder attributes.
+    pub fn check(&self, py: Python, source: &str, file_name: &str) -> PyResult<()> {
+        self.check.call1(py, (source, file_name)).map(|_| ())
+    }
+
+    /// Map a Python error, turning a `SandboxViolation` into
+    /// `TransformError::Sandbox`.
+    pub fn error(&self, py: Python, err: PyErr) -> TransformError {
+        if err.matches(py, self.violation.clone_ref(py)) {
+            let message = err
+                .pvalue(py)
+                .str()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            return TransformError::Sandbox(message);
+        }
+
+        TransformError::from_py(py, err)
+    }
+}
+
+#[cfg(all(test, feature = "cpython"))]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::engine::Engine;
+
+    fn run(engine: &Engine, code: &str) -> Result<Value, TransformError> {
+        engine.transform_with_custom_code(&json!("value"), code)
+    }
+
+    fn denied(code: &str) -> String {
+        match run(&Engine::new().unwrap(), code) {
+            Err(TransformError::Sandbox(message)) => message,
+            other => panic!("{:?} was not denied: {:?}", code, other),
+        }
+    }
+
+    #[test]
+    fn import_outside_the_allow_list_names_the_module() {
+        assert_eq!(
+            denied("import os\noutput = 1"),
+            "import of module 'os' is not allowed"
+        );
+        assert_eq!(
+            denied("from subprocess import run\noutput = 1"),
+            "import of module 'subprocess' is not allowed"
+        );
+        assert_eq!(
+            denied("import os.path\noutput = 1"),
+            "import of module 'os.path' is not allowed"
+        );
+    }
+
+    #[test]
+    fn allowed_modules_import() {
+        let engine = Engine::new().unwrap();
+        assert_eq!(
+            run(&engine, "import math\noutput = math.floor(2.5)"),
+            Ok(json!(2))
+        );
+        assert_eq!(
+            run(&engine, "from json import dumps\noutput = dumps([1])"),
+            Ok(json!("[1]"))
+        );
+    }
+
+    #[test]
+    fn policy_extends_the_allow_list() {
+        let engine = Engine::builder()
+            .sandbox(SandboxPolicy::default().allow_module("string"))
+            .build()
+            .unwrap();
+        assert_eq!(
+            run(&engine, "import string\noutput = string.digits"),
+            Ok(json!("0123456789"))
+        );
+    }
+
+    #[test]
+    fn dunder_import_is_denied() {
+        assert_eq!(
+            denied("output = __import__('os')"),
+            "access to name '__import__' is not allowed (line 1)"
+        );
+    }
+
+    #[test]
+    fn dunder_walks_are_denied() {
+        assert_eq!(
+            denied("output = ().__class__.__bases__[0].__subclasses__()"),
+            "access to attribute '__subclasses__' is not allowed (line 1)"
+        );
+        assert_eq!(
+            denied("x = 1\noutput = value.__class__"),
+            "access to attribute '__class__' is not allowed (line 2)"
+        );
+    }
+
+    #[test]
+    fn private_attributes_are_denied() {
+        assert_eq!(
+            denied("output = field_transformer._rfc3339_to_timestamp(value)"),
+            "access to attribute '_rfc3339_to_timestamp' is not allowed (line 1)"
+        );
+    }
+
+    #[test]
+    fn private_and_nested_methods_are_denied() {
+        assert_eq!(
+            denied("output = field_transformer.transform(value, '_rfc3339_to_timestamp')"),
+            "method '_rfc3339_to_timestamp' is not allowed"
+        );
+        assert_eq!(
+            denied("output = field_transformer.transform_with_custom_code(value, 'output = 1')"),
+            "nested custom code is not allowed"
+        );
+    }
+
+    #[test]
+    fn builtins_outside_the_table_are_undefined() {
+        let engine = Engine::new().unwrap();
+        for code in [
+            "output = open('/etc/passwd')",
+            "output = getattr(value, 'upper')",
+        ] {
+            match run(&engine, code) {
+                Err(TransformError::Python { type_name, .. }) => assert_eq!(type_name, "NameError"),
+                other => panic!("{:?} ran: {:?}", code, other),
+            }
+        }
+    }
+
+    #[test]
+    fn without_sandbox_anything_goes() {
+        let engine = Engine::builder().without_sandbox().build().unwrap();
+        assert_eq!(
+            run(&engine, "import os\noutput = os.sep"),
+            Ok(json!(std::path::MAIN_SEPARATOR.to_string()))
+        );
+    }
+}
@@ -0,0 +1,944 @@
+//! Declarative mapping of output fields to FieldTransformer methods.
+//!
+//! A spec lists, for each output field, where its input lives in the
+//! record, which method transforms it and with which keyword arguments:
+//!
+//! ```yaml
+//! fields:
+//!   - name: created_date
+//!     source: created_at
+//!     method: to_date_index
+//!   - name: local_date
+//!     source: hwlog.time
+//!     method: hwlog_time_to_local_date_index
+//!     params: { tz_offset_in_minute: 420 }
+//!   - name: error_step
+//!     method: extract_app_log_error_code
+//!     params: { code_field: code, part: step }
+//!     on_error: null-field
+//!   - name: sync_duration
+//!     method: calculate_duration
+//!     params: { start: sync_start, end: sync_end }
+//!   - name: ate
+//!     source: ate_id
+//!     method: extract_ate_id
+//!     outputs: { category: ate_category, factory: factory, city: city }
+//!   - name: app
+//!     source: hwlog.crc32
+//!     lookup: app_names
+//!   - name: country
+//!     source: location.country
+//!     default: unknown
+//! tables:
+//!   app_names: { path: crc32_app_names.csv, default: unknown }
+//! on_error: fail-record
+//! ```
+//!
+//! Record-scoped methods (see `record`) are handed the record rather than
+//! a source value, cut down to the paths they read; a record lacking one
+//! of their required paths fails with `MissingInput` unless the field has
+//! a default. Methods from a custom module can be made record-scoped by
+//! listing the paths they need under `inputs`.
+//!
+//! A field with `outputs` writes the named values of a multi-output
+//! method (see `outputs`) to the listed output fields rather than its
+//! result to `name`. They are written together or, when the method fails,
+//! not at all; `null-field` and `keep-original` then set every one of
+//! them to null.
+//!
+//! A field with `lookup` maps its value through one of the spec's
+//! `tables` (see `lookup`) instead of calling a method.
+//!
+//! The same structure can be written as JSON.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    backend::ScriptBackend,
+    deadletter::DeadLetter,
+    error::TransformError,
+    lookup::{LookupTables, TableSpec},
+    outputs,
+    record::{self, InputPath},
+};
+
+/// Source path that selects the whole record.
+pub const WHOLE_RECORD: &str = "$";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    pub fields: Vec<FieldSpec>,
+    /// Policy for fields that do not set their own.
+    #[serde(default)]
+    pub on_error: ErrorPolicy,
+    /// Lookup tables, by name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tables: BTreeMap<String, TableSpec>,
+    #[serde(skip)]
+    lookups: LookupTables,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldSpec {
+    /// Output field name; names the field in dead letters only when it
+    /// has `outputs`.
+    pub name: String,
+    /// Dot-separated path of the input value, with numeric segments
+    /// indexing arrays; `$` is the whole record. Defaults to `name`, or
+    /// to `$` for record-scoped methods.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// FieldTransformer method to call; without one the value is copied.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    /// Table of the spec to map the value through, instead of a method.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lookup: Option<String>,
+    /// Keyword arguments for `method`.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub params: Map<String, Value>,
+    /// Record paths `method` requires, making it record-scoped.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<String>,
+    /// Output fields for the named values of a multi-output `method`,
+    /// keyed by value name. The values a method does not declare are
+    /// taken from a dict result.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub outputs: BTreeMap<String, String>,
+    /// Output when the source path, or a required input of a
+    /// record-scoped method, is not in the record; the method is not
+    /// called then. Without a default the field is null, or fails with
+    /// `MissingInput` for a missing input.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    /// What to do when `method` raises; the spec's `on_error` if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_error: Option<ErrorPolicy>,
+}
+
+/// What to do with a record when one of its fields fails to transform.
+///
+/// Every failure is reported as a `DeadLetter` whatever the policy.
+/// `fail-record` corresponds to `FieldTransformer(strict=True)`, where the
+/// exception propagates, and `keep-original` to `strict=False`, where the
+/// input value is passed through. Record-scoped fields have no one input
+/// value, so `keep-original` sets them to null.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorPolicy {
+    /// Drop the record and go on with the next one.
+    #[default]
+    FailRecord,
+    /// Output null for the field and keep the record.
+    NullField,
+    /// Output the field's input value unchanged and keep the record; null
+    /// for record-scoped fields.
+    KeepOriginal,
+    /// Drop the record and stop processing the batch.
+    AbortBatch,
+}
+
+/// Result of applying a spec to one record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Applied {
+    /// The output record, in spec order; none when a `fail-record` or
+    /// `abort-batch` field failed.
+    pub output: Option<Map<String, Value>>,
+    /// One per failed field.
+    pub dead_letters: Vec<DeadLetter>,
+    /// An `abort-batch` field failed.
+    pub abort: bool,
+}
+
+impl Spec {
+    /// Read a spec file: JSON if it ends in `.json`, YAML otherwise. Table
+    /// paths are relative to the directory of the file.
+    pub fn load(path: &Path) -> Result<Spec, TransformError> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| TransformError::Load(format!("{}: {}", path.display(), err)))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        let spec = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&text).map_err(|err| TransformError::Load(err.to_string()))
+        } else {
+            serde_yaml::from_str(&text).map_err(|err| TransformError::Load(err.to_string()))
+        };
+
+        spec.and_then(|spec: Spec| spec.open(base))
+            .map_err(|err| match err {
+                TransformError::Load(message) => {
+                    TransformError::Load(format!("{}: {}", path.display(), message))
+                }
+                err => err,
+            })
+    }
+
+    /// Parse a YAML spec; table paths are relative to the working
+    /// directory.
+    pub fn from_yaml(text: &str) -> Result<Spec, TransformError> {
+        let spec: Spec =
+            serde_yaml::from_str(text).map_err(|err| TransformError::Load(err.to_string()))?;
+        spec.open(Path::new(""))
+    }
+
+    /// Parse a JSON spec; table paths are relative to the working
+    /// directory.
+    pub fn from_json(text: &str) -> Result<Spec, TransformError> {
+        let spec: Spec =
+            serde_json::from_str(text).map_err(|err| TransformError::Load(err.to_string()))?;
+        spec.open(Path::new(""))
+    }
+
+    /// Validate a parsed spec and read its tables.
+    fn open(mut self, base: &Path) -> Result<Spec, TransformError> {
+        self.validate()?;
+        self.lookups = LookupTables::open(&self.tables, base)?;
+        Ok(self)
+    }
+
+    /// Read every lookup table whose file changed since it was last read.
+    /// Tables are also reloaded as they are used, unless they set
+    /// `reload: false`.
+    pub fn reload_tables(&self) -> Result<Vec<String>, TransformError> {
+        self.lookups.reload()
+    }
+
+    /// Check that output names are unique, every path is well formed,
+    /// record-scoped methods have the params that name their inputs,
+    /// multi-output methods have the outputs asked of them and lookups
+    /// name a table.
+    pub fn validate(&self) -> Result<(), TransformError> {
+        let mut names = HashSet::new();
+        for field in &self.fields {
+            if field.name.is_empty() {
+                return Err(TransformError::Load("field name must not be empty".into()));
+            }
+            if !names.insert(field.name.as_str()) {
+                return Err(TransformError::Load(format!(
+                    "field '{}' is defined more than once",
+                    field.name
+                )));
+            }
+            let source = field.source();
+            if source != WHOLE_RECORD && source.split('.').any(str::is_empty) {
+                return Err(TransformError::Load(format!(
+                    "field '{}': invalid source path '{}'",
+                    field.name, source
+                )));
+            }
+            field.record_inputs().map_err(|message| {
+                TransformError::Load(format!("field '{}': {}", field.name, message))
+            })?;
+            match &field.lookup {
+                Some(_) if field.method.is_some() => {
+                    return Err(TransformError::Load(format!(
+                        "field '{}' has both a method and a lookup",
+                        field.name
+                    )))
+                }
+                Some(table) if !self.tables.contains_key(table) => {
+                    return Err(TransformError::Load(format!(
+                        "field '{}': no table '{}'",
+                        field.name, table
+                    )))
+                }
+                _ => {}
+            }
+            field.check_outputs().map_err(|message| {
+                TransformError::Load(format!("field '{}': {}", field.name, message))
+            })?;
+            for destination in field.outputs.values() {
+                if !names.insert(destination.as_str()) {
+                    return Err(TransformError::Load(format!(
+                        "field '{}' is defined more than once",
+                        destination
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Use `policy` for every field, overriding their own settings.
+    pub fn set_policy(&mut self, policy: ErrorPolicy) {
+        self.on_error = policy;
+        for field in &mut self.fields {
+            field.on_error = None;
+        }
+    }
+
+    pub fn policy(&self, field: &FieldSpec) -> ErrorPolicy {
+        field.on_error.unwrap_or(self.on_error)
+    }
+
+    /// Build the output record for `record`, one field per spec entry, in
+    /// spec order, handling failed fields by their policy.
+    pub fn apply(&self, backend: &dyn ScriptBackend, record: &Value) -> Applied {
+        let mut applied = Applied {
+            output: None,
+            dead_letters: Vec::new(),
+            abort: false,
+        };
+
+        let mut output = Map::new();
+        for field in &self.fields {
+            let error = match field.apply_outputs(backend, &self.lookups, record) {
+                Ok(values) => {
+                    output.extend(values);
+                    continue;
+                }
+                Err(error) => error,
+            };
+
+            let policy = self.policy(field);
+            applied.dead_letters.push(DeadLetter {
+                field: Some(field.name.clone()),
+                method: field.method.clone(),
+                params: field.params.clone(),
+                policy: Some(policy),
+                ..DeadLetter::new(record.clone(), error)
+            });
+
+            match policy {
+                ErrorPolicy::NullField | ErrorPolicy::KeepOriginal if !field.outputs.is_empty() => {
+                    for destination in field.outputs.values() {
+                        output.insert(destination.clone(), Value::Null);
+                    }
+                }
+                ErrorPolicy::NullField => {
+                    output.insert(field.name.clone(), Value::Null);
+                }
+                ErrorPolicy::KeepOriginal if field.is_record_scoped() => {
+                    output.insert(field.name.clone(), Value::Null);
+                }
+                ErrorPolicy::KeepOriginal => {
+                    let original = resolve(record, field.source()).cloned();
+                    output.insert(field.name.clone(), original.unwrap_or(Value::Null));
+                }
+                ErrorPolicy::FailRecord => return applied,
+                ErrorPolicy::AbortBatch => {
+                    applied.abort = true;
+                    return applied;
+                }
+            }
+        }
+
+        applied.output = Some(output);
+        applied
+    }
+}
+
+impl FieldSpec {
+    pub fn source(&self) -> &str {
+        match &self.source {
+            Some(source) => source,
+            None if self.is_record_scoped() => WHOLE_RECORD,
+            None => &self.name,
+        }
+    }
+
+    /// Whether `method` takes the whole record.
+    pub fn is_record_scoped(&self) -> bool {
+        !self.inputs.is_empty()
+            || self
+                .method
+                .as_deref()
+                .is_some_and(|m| record::lookup(m).is_some())
+    }
+
+    /// The record paths a record-scoped method reads, None for other
+    /// fields.
+    pub fn record_inputs(&self) -> Result<Option<Vec<InputPath>>, String> {
+        if !self.is_record_scoped() {
+            return Ok(None);
+        }
+        let method = self.method.as_deref().ok_or("inputs need a method")?;
+        if self.source() != WHOLE_RECORD {
+            return Err(format!("{} takes the whole record, not a source", method));
+        }
+
+        let mut inputs = match record::lookup(method) {
+            Some(scoped) => scoped.input_paths(&self.params)?,
+            None => Vec::new(),
+        };
+        inputs.extend(self.inputs.iter().map(|path| InputPath {
+            path: path.clone(),
+            required: true,
+            key: false,
+        }));
+        if let Some(input) = inputs.iter().find(|input| {
+            !input.key && input.path != WHOLE_RECORD && input.path.split('.').any(str::is_empty)
+        }) {
+            return Err(format!("invalid input path '{}'", input.path));
+        }
+        Ok(Some(inputs))
+    }
+
+    fn check_outputs(&self) -> Result<(), String> {
+        if self.outputs.is_empty() {
+            return Ok(());
+        }
+        let method = self.method.as_deref().ok_or("outputs need a method")?;
+        if let Some(declared) = outputs::lookup(method) {
+            if let Some(name) = self
+                .outputs
+                .keys()
+                .find(|name| !declared.outputs.contains(&name.as_str()))
+            {
+                return Err(format!("{} has no output '{}'", method, name));
+            }
+        }
+        match self
+            .outputs
+            .values()
+            .find(|destination| destination.is_empty())
+        {
+            Some(_) => Err("output field names must not be empty".into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Transform this field's input in `record`, looking it up in
+    /// `tables` if the field has a `lookup`.
+    pub fn apply(
+        &self,
+        backend: &dyn ScriptBackend,
+        tables: &LookupTables,
+        record: &Value,
+    ) -> Result<Value, TransformError> {
+        Ok(self
+            .evaluate(backend, tables, record)?
+            .or_else(|| self.default.clone())
+            .unwrap_or(Value::Null))
+    }
+
+    /// The output fields this field writes for `record`: its `outputs` in
+    /// the order the method returns them, or just `name`.
+    pub fn apply_outputs(
+        &self,
+        backend: &dyn ScriptBackend,
+        tables: &LookupTables,
+        record: &Value,
+    ) -> Result<Vec<(String, Value)>, TransformError> {
+        if self.outputs.is_empty() {
+            let value = self.apply(backend, tables, record)?;
+            return Ok(vec![(self.name.clone(), value)]);
+        }
+
+        let method = self.method.as_deref().unwrap_or_default();
+        let values = match self
+            .evaluate(backend, tables, record)?
+            .or_else(|| self.default.clone())
+        {
+            Some(result) => outputs::unpack(method, result)?,
+            None => {
+                let nulls = self
+                    .outputs
+                    .values()
+                    .map(|destination| (destination.clone(), Value::Null));
+                return Ok(nulls.collect());
+            }
+        };
+        if let Some(name) = self.outputs.keys().find(|name| !values.contains_key(*name)) {
+            return Err(TransformError::Conversion(format!(
+                "{} returned no '{}'",
+                method, name
+            )));
+        }
+
+        Ok(values
+            .into_iter()
+            .filter_map(|(name, value)| Some((self.outputs.get(&name)?.clone(), value)))
+            .collect())
+    }
+
+    /// The method's result, None when the input is not in the record and
+    /// the default applies.
+    fn evaluate(
+        &self,
+        backend: &dyn ScriptBackend,
+        tables: &LookupTables,
+        record: &Value,
+    ) -> Result<Option<Value>, TransformError> {
+        if let Some(inputs) = self.record_inputs().map_err(TransformError::Load)? {
+            let projection = match (record::project(record, &inputs), &self.default) {
+                (Ok(projection), _) => projection,
+                (Err(_), Some(_)) => return Ok(None),
+                (Err(err), None) => return Err(err),
+            };
+            let method = self.method.as_deref().unwrap_or_default();
+            return backend
+                .transform(&projection, method, &self.params)
+                .map(Some);
+        }
+
+        let value = match resolve(record, self.source()) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        match (&self.method, &self.lookup) {
+            (Some(method), _) => backend.transform(value, method, &self.params).map(Some),
+            (None, Some(table)) => match tables.get(table) {
+                Some(table) => Ok(Some(table.lookup(value))),
+                None => Err(TransformError::Load(format!("no table '{}'", table))),
+            },
+            (None, None) => Ok(Some(value.clone())),
+        }
+    }
+}
+
+/// The value at a source path, None if any segment is missing.
+pub fn resolve<'a>(record: &'a Value, path: &str) -> Option<&'a Value> {
+    if path == WHOLE_RECORD {
+        return Some(record);
+    }
+
+    path.split('.')
+        .try_fold(record, |value, segment| match value {
+            Value::Object(map) => map.get(segment),
+            Value::Array(items) => segment
+                .parse()
+                .ok()
+                .and_then(|index: usize| items.get(index)),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        backend::{BackendKind, Snippet},
+        error::ErrorCategory,
+        loader::FieldTransformerSource,
+        native::py_str,
+    };
+
+    /// Lowercases for `lowercase`, splits on `.` into a dict for
+    /// `split_pair`, answers `extract_ate_id` natively; every other method
+    /// raises.
+    struct Stub;
+
+    impl ScriptBackend for Stub {
+        fn kind(&self) -> BackendKind {
+            BackendKind::Pyo3
+        }
+
+        fn load_module(&mut self, _: &FieldTransformerSource) -> Result<(), TransformError> {
+            Ok(())
+        }
+
+        fn compile(&self, custom_code: &str) -> Result<Snippet, TransformError> {
+            Ok(Snippet::new(custom_code))
+        }
+
+        fn call(&self, _: &Snippet, value: &Value) -> Result<Value, TransformError> {
+            Ok(value.clone())
+        }
+
+        fn transform(
+            &self,
+            value: &Value,
+            method_name: &str,
+            params: &Map<String, Value>,
+        ) -> Result<Value, TransformError> {
+            match method_name {
+                "lowercase" => Ok(json!(py_str(value).to_lowercase())),
+                "split_pair" => match py_str(value).split_once('.') {
+                    Some((left, right)) => Ok(json!({"left": left, "right": right})),
+                    None => Ok(json!([py_str(value)])),
+                },
+                "extract_ate_id" => crate::native::lookup(method_name).unwrap()(value, params),
+                _ => Err(failure(method_name)),
+            }
+        }
+    }
+
+    fn failure(method: &str) -> TransformError {
+        TransformError::Python {
+            type_name: "ValueError".into(),
+            message: format!("{} failed", method),
+            traceback: String::new(),
+        }
+    }
+
+    fn record() -> Value {
+        json!({"id": 7, "brand": "FOSSIL", "created_at": {"$date": 1514772122123i64}})
+    }
+
+    fn spec(policy: &str) -> Spec {
+        Spec::from_yaml(&format!(
+            "
+fields:
+  - name: id
+  - name: brand
+    method: lowercase
+  - name: date
+    source: created_at
+    method: to_date_index
+    params: {{ strict: true }}
+    on_error: {}
+  - name: after
+    source: id
+",
+            policy
+        ))
+        .unwrap()
+    }
+
+    fn dead_letter(policy: ErrorPolicy) -> DeadLetter {
+        let mut params = Map::new();
+        params.insert("strict".into(), json!(true));
+        DeadLetter {
+            field: Some("date".into()),
+            method: Some("to_date_index".into()),
+            params,
+            policy: Some(policy),
+            ..DeadLetter::new(record(), failure("to_date_index"))
+        }
+    }
+
+    fn output(value: Value) -> Option<Map<String, Value>> {
+        match value {
+            Value::Object(map) => Some(map),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn fail_record_drops_the_record() {
+        let applied = spec("fail-record").apply(&Stub, &record());
+        assert_eq!(
+            applied,
+            Applied {
+                output: None,
+                dead_letters: vec![dead_letter(ErrorPolicy::FailRecord)],
+                abort: false,
+            }
+        );
+    }
+
+    #[test]
+    fn null_field_keeps_the_record() {
+        let applied = spec("null-field").apply(&Stub, &record());
+        assert_eq!(
+            applied,
+            Applied {
+                output: output(json!({"id": 7, "brand": "fossil", "date": null, "after": 7})),
+                dead_letters: vec![dead_letter(ErrorPolicy::NullField)],
+                abort: false,
+            }
+        );
+    }
+
+    #[test]
+    fn keep_original_passes_the_input_through() {
+        let applied = spec("keep-original").apply(&Stub, &record());
+        assert_eq!(
+            applied,
+            Applied {
+                output: output(json!({
+                    "id": 7,
+                    "brand": "fossil",
+                    "date": {"$date": 1514772122123i64},
+                    "after": 7
+                })),
+                dead_letters: vec![dead_letter(ErrorPolicy::KeepOriginal)],
+                abort: false,
+            }
+        );
+    }
+
+    #[test]
+    fn abort_batch_drops_the_record_and_stops() {
+        let applied = spec("abort-batch").apply(&Stub, &record());
+        assert_eq!(
+            applied,
+            Applied {
+                output: None,
+                dead_letters: vec![dead_letter(ErrorPolicy::AbortBatch)],
+                abort: true,
+            }
+        );
+    }
+
+    #[test]
+    fn keep_original_nulls_record_scoped_fields() {
+        let spec = Spec::from_yaml(
+            "
+fields:
+  - name: duration
+    method: calculate_duration
+    params: { start: created_at, end: id }
+    on_error: keep-original
+",
+        )
+        .unwrap();
+        let applied = spec.apply(&Stub, &record());
+
+        assert_eq!(applied.output, output(json!({"duration": null})));
+        assert_eq!(applied.dead_letters.len(), 1);
+        assert_eq!(applied.dead_letters[0].record, record());
+        assert_eq!(applied.dead_letters[0].category, ErrorCategory::Python);
+    }
+
+    #[test]
+    fn set_policy_overrides_every_field() {
+        let mut spec = spec("null-field");
+        spec.set_policy(ErrorPolicy::FailRecord);
+        assert_eq!(spec.apply(&Stub, &record()).output, None);
+    }
+
+    #[test]
+    fn spec_policy_applies_to_fields_without_one() {
+        let spec = Spec::from_yaml(
+            "
+on_error: fail-record
+fields:
+  - name: id
+  - name: date
+    source: created_at
+    method: to_date_index
+    params: { strict: true }
+",
+        )
+        .unwrap();
+        assert_eq!(spec.policy(&spec.fields[1]), ErrorPolicy::FailRecord);
+        assert_eq!(spec.apply(&Stub, &record()).output, None);
+
+        let spec = Spec::from_yaml(
+            "
+on_error: fail-record
+fields:
+  - name: id
+  - name: date
+    source: created_at
+    method: to_date_index
+    params: { strict: true }
+    on_error: null-field
+",
+        )
+        .unwrap();
+        assert_eq!(spec.policy(&spec.fields[1]), ErrorPolicy::NullField);
+        assert_eq!(
+            spec.apply(&Stub, &record()).output.map(Value::Object),
+            Some(json!({"id": 7, "date": null}))
+        );
+    }
+
+    #[test]
+    fn outputs_fan_out_into_several_fields() {
+        let spec = Spec::from_yaml(
+            "
+fields:
+  - name: id
+  - name: ate
+    source: ate_id
+    method: extract_ate_id
+    outputs: { category: ate_category, factory: factory, city: city }
+  - name: pair
+    source: code
+    method: split_pair
+    outputs: { left: code_left, right: code_right }
+",
+        )
+        .unwrap();
+        let record = json!({"id": 1, "ate_id": "DW.MM.1-FLEX.2.ZH", "code": "a.b"});
+        let applied = spec.apply(&Stub, &record);
+
+        assert_eq!(applied.dead_letters, []);
+        assert_eq!(
+            applied.output.map(Value::Object),
+            Some(json!({
+                "id": 1,
+                "ate_category": "DW",
+                "factory": "FLEX",
+                "city": "ZH",
+                "code_left": "a",
+                "code_right": "b",
+            }))
+        );
+    }
+
+    #[test]
+    fn failed_fan_outs_write_nothing_or_nulls() {
+        let spec = |policy: &str| {
+            Spec::from_yaml(&format!(
+                "
+fields:
+  - name: pair
+    source: code
+    method: split_pair
+    outputs: {{ left: code_left, right: code_right }}
+    on_error: {}
+",
+                policy
+            ))
+            .unwrap()
+        };
+        let record = json!({"code": "ab"});
+        let error = TransformError::Conversion(
+            "split_pair returned an undeclared tuple where named outputs were expected".into(),
+        );
+
+        let failed = spec("fail-record").apply(&Stub, &record);
+        assert_eq!(failed.output, None);
+        assert_eq!(failed.dead_letters[0].error, error);
+
+        let nulled = spec("null-field").apply(&Stub, &record);
+        assert_eq!(
+            nulled.output.map(Value::Object),
+            Some(json!({"code_left": null, "code_right": null}))
+        );
+    }
+
+    fn rejected(yaml: &str) -> String {
+        match Spec::from_yaml(yaml) {
+            Err(TransformError::Load(message)) => message,
+            other => panic!("spec was accepted: {:?}", other.map(|spec| spec.fields)),
+        }
+    }
+
+    #[test]
+    fn validate_rejects_duplicate_output_names() {
+        assert_eq!(
+            rejected("fields: [{ name: a }, { name: a, source: b }]"),
+            "field 'a' is defined more than once"
+        );
+        assert_eq!(
+            rejected(
+                "fields:
+  - name: city
+  - name: ate
+    method: extract_ate_id
+    outputs: { city: city }"
+            ),
+            "field 'city' is defined more than once"
+        );
+        assert_eq!(
+            rejected("fields: [{ name: '' }]"),
+            "field name must not be empty"
+        );
+    }
+
+    #[test]
+    fn validate_rejects_bad_paths() {
+        assert_eq!(
+            rejected("fields: [{ name: a, source: 'b..c' }]"),
+            "field 'a': invalid source path 'b..c'"
+        );
+        assert_eq!(
+            rejected("fields: [{ name: a, source: 'b.' }]"),
+            "field 'a': invalid source path 'b.'"
+        );
+        assert_eq!(
+            rejected("fields: [{ name: a, method: custom, inputs: ['x..y'] }]"),
+            "field 'a': invalid input path 'x..y'"
+        );
+        assert_eq!(
+            rejected("fields: [{ name: a, source: b, method: calculate_duration }]"),
+            "field 'a': calculate_duration takes the whole record, not a source"
+        );
+        assert_eq!(
+            rejected("fields: [{ name: a, method: calculate_duration, params: { start: s } }]"),
+            "field 'a': calculate_duration needs param 'end'"
+        );
+    }
+
+    #[test]
+    fn validate_rejects_bad_lookups() {
+        assert_eq!(
+            rejected("fields: [{ name: a, lookup: apps }]"),
+            "field 'a': no table 'apps'"
+        );
+        assert_eq!(
+            rejected("fields: [{ name: a, method: lowercase, lookup: apps }]"),
+            "field 'a' has both a method and a lookup"
+        );
+    }
+
+    #[test]
+    fn validate_rejects_bad_outputs() {
+        assert_eq!(
+            rejected("fields: [{ name: a, outputs: { x: y } }]"),
+            "field 'a': outputs need a method"
+        );
+        assert_eq!(
+            rejected("fields: [{ name: a, method: extract_ate_id, outputs: { town: t } }]"),
+            "field 'a': extract_ate_id has no output 'town'"
+        );
+    }
+
+    #[test]
+    fn missing_sources_take_the_default() {
+        let spec = Spec::from_yaml(
+            "
+fields:
+  - name: brand
+    method: lowercase
+    default: none
+  - name: country
+    source: location.country
+  - name: duration
+    method: calculate_duration
+    params: { start: s, end: e }
+    inputs: [session.id]
+    default: -1
+",
+        )
+        .unwrap();
+        assert_eq!(spec.on_error, ErrorPolicy::FailRecord);
+
+        let applied = spec.apply(&Stub, &json!({"location": {}}));
+        assert_eq!(applied.dead_letters, []);
+        assert_eq!(
+            applied.output.map(Value::Object),
+            Some(json!({"brand": "none", "country": null, "duration": -1}))
+        );
+    }
+
+    #[test]
+    fn nested_sources_are_read_into_flat_outputs() {
+        let spec = Spec::from_yaml(
+            "
+fields:
+  - name: country
+    source: location.country
+    method: lowercase
+  - name: first_tag
+    source: tags.0.name
+  - name: whole
+    source: $
+  - name: location.city
+    source: location.city
+",
+        )
+        .unwrap();
+        let record = json!({
+            "location": {"country": "VN", "city": "HCM"},
+            "tags": [{"name": "a"}, {"name": "b"}],
+        });
+        let applied = spec.apply(&Stub, &record);
+
+        let mut expected = json!({
+            "country": "vn",
+            "first_tag": "a",
+            "location.city": "HCM",
+        });
+        expected["whole"] = record.clone();
+        assert_eq!(applied.output.map(Value::Object), Some(expected));
+        assert_eq!(resolve(&record, "tags.2.name"), None);
+        assert_eq!(resolve(&record, "location.country.code"), None);
+    }
+}
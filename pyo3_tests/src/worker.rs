@@ -0,0 +1,139 @@
+//! Line-delimited JSON protocol spoken by `transform-worker` processes.
+//!
+//! The host writes one `WorkerConfig` line, the worker answers with
+//! `{"Ok":null}` once its engine is loaded (or `{"Err":...}`), then every
+//! `Request` line gets exactly one `Response` line back.
+
+use std::{
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    engine::{Engine, EngineBuilder},
+    error::TransformError,
+    loader::FieldTransformerSource,
+    sandbox::SandboxPolicy,
+};
+
+/// Serializable engine settings, shared by every worker of a pool.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkerConfig {
+    /// FieldTransformer module to load; the embedded test copy when `None`.
+    pub source: Option<PathBuf>,
+    pub timeout_ms: Option<u64>,
+    pub max_operations: Option<u64>,
+    pub sandbox: SandboxPolicy,
+    pub unsandboxed: bool,
+}
+
+impl WorkerConfig {
+    pub fn builder(&self) -> EngineBuilder {
+        let mut builder = Engine::builder();
+        if let Some(path) = &self.source {
+            builder = builder.source(FieldTransformerSource::Path(path.clone()));
+        }
+        if let Some(timeout_ms) = self.timeout_ms {
+            builder = builder.timeout(Duration::from_millis(timeout_ms));
+        }
+        if let Some(max_operations) = self.max_operations {
+            builder = builder.max_operations(max_operations);
+        }
+        if self.unsandboxed {
+            builder.without_sandbox()
+        } else {
+            builder.sandbox(self.sandbox.clone())
+        }
+    }
+}
+
+/// One unit of work for an engine.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Task {
+    CustomCode {
+        value: Value,
+        custom_code: String,
+    },
+    Method {
+        value: Value,
+        method: String,
+        #[serde(default)]
+        params: Map<String, Value>,
+    },
+}
+
+impl Task {
+    pub fn run(&self, engine: &Engine) -> Result<Value, TransformError> {
+        match self {
+            Task::CustomCode { value, custom_code } => {
+                engine.transform_with_custom_code(value, custom_code)
+            }
+            Task::Method {
+                value,
+                method,
+                params,
+            } => engine.transform(value, method, params),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Request {
+    pub id: u64,
+    pub task: Task,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Response {
+    pub id: u64,
+    pub result: Result<Value, TransformError>,
+}
+
+/// Serve the worker protocol until `input` is closed.
+pub fn serve<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<()> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(());
+    }
+
+    let engine = serde_json::from_str::<WorkerConfig>(&line)
+        .map_err(|err| TransformError::Load(format!("invalid worker config: {}", err)))
+        .and_then(|config| config.builder().build());
+    let engine = match engine {
+        Ok(engine) => {
+            write_line(&mut output, &Ok::<(), TransformError>(()))?;
+            engine
+        }
+        Err(err) => return write_line(&mut output, &Err::<(), _>(err)),
+    };
+
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let request: Request = serde_json::from_str(&line)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        let result = request.task.run(&engine);
+        write_line(
+            &mut output,
+            &Response {
+                id: request.id,
+                result,
+            },
+        )?;
+    }
+}
+
+pub(crate) fn write_line<W: Write, T: Serialize>(output: &mut W, message: &T) -> io::Result<()> {
+    serde_json::to_writer(&mut *output, message)?;
+    output.write_all(b"\n")?;
+    output.flush()
+}
@@ -0,0 +1,182 @@
+//! The `field-transform` binary end to end: spec and custom-code modes,
+//! `--strict`, the dead-letter file and malformed input lines.
+
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+use serde_json::{json, Value};
+
+const SPEC: &str = "
+fields:
+  - name: id
+  - name: brand
+    method: lowercase
+  - name: date
+    source: created_at
+    method: to_date_index
+    on_error: null-field
+";
+
+const RECORDS: &str = r#"{"id": 1, "brand": "FOSSIL", "created_at": {"$date": 1514772122123}}
+{"id": 2, "brand": "SKAGEN", "created_at": "yesterday"}
+not json
+{"id": 3, "brand": "MK", "created_at": {"$date": 1514772122123}}
+"#;
+
+/// A scratch directory for one test, removed when dropped.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new(test: &str) -> Self {
+        let dir = env::temp_dir().join(format!("pyo3_tests-cli-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        Scratch(dir)
+    }
+
+    fn file(&self, name: &str, contents: &str) -> String {
+        let path = self.0.join(name);
+        fs::write(&path, contents).unwrap();
+        path.display().to_string()
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.0.join(name).display().to_string()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Run `field-transform` with `args`, feeding `stdin`.
+fn field_transform(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_field-transform"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn lines(ndjson: &[u8]) -> Vec<Value> {
+    String::from_utf8_lossy(ndjson)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn spec_mode_transforms_every_record() {
+    let scratch = Scratch::new("spec");
+    let spec = scratch.file("spec.yaml", SPEC);
+    let output = field_transform(&["-s", &spec], RECORDS);
+
+    assert!(output.status.success());
+    assert_eq!(
+        lines(&output.stdout),
+        [
+            json!({"id": 1, "brand": "fossil", "date": 20180101}),
+            json!({"id": 2, "brand": "skagen", "date": null}),
+            json!({"id": 3, "brand": "mk", "date": 20180101}),
+        ]
+    );
+
+    let dead_letters = lines(&output.stderr);
+    assert_eq!(dead_letters.len(), 2);
+    assert_eq!(dead_letters[0]["field"], "date");
+    assert_eq!(dead_letters[0]["policy"], "null-field");
+    assert_eq!(dead_letters[0]["input"], "-");
+    assert_eq!(dead_letters[0]["line"], 2);
+}
+
+#[test]
+fn custom_code_mode_runs_the_snippet_per_record() {
+    let output = field_transform(
+        &[
+            "-c",
+            "output = {'id': value['id'], 'twice': value['id'] * 2}",
+        ],
+        RECORDS,
+    );
+
+    assert!(output.status.success());
+    assert_eq!(
+        lines(&output.stdout),
+        [
+            json!({"id": 1, "twice": 2}),
+            json!({"id": 2, "twice": 4}),
+            json!({"id": 3, "twice": 6}),
+        ]
+    );
+    assert_eq!(lines(&output.stderr).len(), 1);
+}
+
+#[test]
+fn strict_stops_at_the_first_failure() {
+    let scratch = Scratch::new("strict");
+    let spec = scratch.file("spec.yaml", SPEC);
+    let output = field_transform(&["-s", &spec, "--strict"], RECORDS);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        lines(&output.stdout),
+        [json!({"id": 1, "brand": "fossil", "date": 20180101})]
+    );
+    let dead_letters = lines(&output.stderr);
+    assert_eq!(dead_letters.len(), 1);
+    assert_eq!(dead_letters[0]["policy"], "abort-batch");
+    assert_eq!(dead_letters[0]["line"], 2);
+}
+
+#[test]
+fn dead_letters_go_to_the_errors_file() {
+    let scratch = Scratch::new("errors");
+    let input = scratch.file("records.jsonl", RECORDS);
+    let errors = scratch.path("errors.jsonl");
+    let output = field_transform(&["-c", "output = value['id']", "-e", &errors, &input], "");
+
+    assert!(output.status.success());
+    assert_eq!(lines(&output.stdout), [json!(1), json!(2), json!(3)]);
+    assert!(output.stderr.is_empty());
+    assert_eq!(
+        lines(&fs::read(&errors).unwrap()),
+        [json!({
+            "record": "not json",
+            "policy": "fail-record",
+            "category": "conversion",
+            "error": {"conversion": "invalid JSON: expected ident at line 1 column 2"},
+            "input": input,
+            "line": 3,
+        })]
+    );
+}
+
+#[test]
+fn malformed_lines_are_dead_lettered_and_skipped() {
+    let output = field_transform(
+        &["-c", "output = value"],
+        "{\"id\": 1}\n{\"id\": \n\n[1, 2]\n",
+    );
+
+    assert!(output.status.success());
+    assert_eq!(lines(&output.stdout), [json!({"id": 1}), json!([1, 2])]);
+    let dead_letters = lines(&output.stderr);
+    assert_eq!(dead_letters.len(), 1);
+    assert_eq!(dead_letters[0]["record"], "{\"id\": ");
+    assert_eq!(dead_letters[0]["category"], "conversion");
+    assert_eq!(dead_letters[0]["line"], 2);
+}
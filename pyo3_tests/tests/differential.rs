@@ -0,0 +1,357 @@
+//! Native ports against the embedded test copy of FieldTransformer.
+//!
+//! Every method with a native port is called on the same generated inputs
+//! through an engine with native transforms off and through the port, and
+//! every difference is reported with the input that caused it. Results
+//! must be equal; errors must have the same exception type and message,
+//! or the same category. `DIFFERENTIAL_SEED` and `DIFFERENTIAL_CASES`
+//! widen the search.
+//!
+//! Differences the native modules document as extensions do not fail the
+//! test; see `documented`. `DIFFERENTIAL_VERBOSE` prints how many there
+//! were of each.
+
+use std::{collections::BTreeMap, env};
+
+use pyo3_tests::{native, Engine, TransformError};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde_json::{json, Map, Value};
+
+const DEFAULT_SEED: u64 = 0x5eed;
+const DEFAULT_CASES: usize = 200;
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// One corpus generator: a kind of input the ports see in production.
+type Generator = fn(&mut StdRng) -> Value;
+
+const GENERATORS: &[Generator] = &[
+    timestamp,
+    datetime_string,
+    bson,
+    serial,
+    version,
+    ate_id,
+    brand_name,
+    crc32,
+];
+
+/// Inputs worth trying on every method.
+fn fixed() -> Vec<Value> {
+    vec![
+        Value::Null,
+        json!(true),
+        json!(false),
+        json!(0),
+        json!(-1),
+        json!(0.0),
+        json!(""),
+        json!(" "),
+        json!([]),
+        json!({}),
+        json!(["a", "1"]),
+        json!({"a": 1}),
+        json!(9_999_999_999u64),
+        json!(10_000_000_000u64),
+        json!(i64::MAX),
+        json!(u64::MAX),
+        json!(1e300),
+        json!("1e3"),
+        json!("nan"),
+    ]
+}
+
+fn timestamp(rng: &mut StdRng) -> Value {
+    // Seconds, then milli-, micro- and nanoseconds of the same span.
+    let seconds = rng.gen_range(-100_000_000i64..4_000_000_000);
+    let scaled = seconds.saturating_mul(10i64.pow(rng.gen_range(0..4) * 3));
+    match rng.gen_range(0..5) {
+        0 | 1 => json!(scaled),
+        2 => json!(scaled as f64 + rng.gen_range(0..1000) as f64 / 1000.0),
+        3 => json!(scaled.to_string()),
+        _ => json!(format!("{}.{}", scaled, rng.gen_range(0..1000))),
+    }
+}
+
+fn datetime_string(rng: &mut StdRng) -> Value {
+    let (year, month, day) = (
+        rng.gen_range(1969..2040),
+        rng.gen_range(1..=13),
+        rng.gen_range(1..=31),
+    );
+    let (hour, minute, second) = (
+        rng.gen_range(0..24),
+        rng.gen_range(0..60),
+        rng.gen_range(0..60),
+    );
+    let micros = rng.gen_range(0..1_000_000);
+    let date = format!("{:04}-{:02}-{:02}", year, month, day);
+    let time = format!("{:02}:{:02}:{:02}", hour, minute, second);
+    json!(match rng.gen_range(0..7) {
+        0 => date,
+        1 => format!("{} {}", date, time),
+        2 => format!("{} {}.{:06}", date, time, micros),
+        3 => format!("{}T{}.{:06}", date, time, micros),
+        4 => format!("{}T{}.{:06}Z", date, time, micros),
+        5 => format!("{}T{}Z", date, time),
+        _ => format!("{}/{}", date, time),
+    })
+}
+
+fn bson(rng: &mut StdRng) -> Value {
+    let millis = rng.gen_range(-1_000_000_000_000i64..4_000_000_000_000);
+    let oid: String = (0..24)
+        .map(|_| *b"0123456789abcdefABCDEF".choose(rng).unwrap() as char)
+        .collect();
+    match rng.gen_range(0..8) {
+        0 => json!({ "$date": millis }),
+        1 => json!({ "$date": datetime_string(rng) }),
+        2 => json!({ "$date": null }),
+        3 => json!({ "$oid": oid }),
+        4 => json!({ "$oid": &oid[..rng.gen_range(0..24)] }),
+        5 => json!({ "$numberLong": millis.to_string() }),
+        6 => json!({ "$numberLong": millis }),
+        _ => json!({ "$date": millis, "other": 1 }),
+    }
+}
+
+fn serial(rng: &mut StdRng) -> Value {
+    const PREFIXES: &[&str] = &["C0D101", "C0K305", "C0M503", "C0S102", "C3F9366"];
+    let pick = |rng: &mut StdRng, alphabet: &[u8]| *alphabet.choose(rng).unwrap() as char;
+    let mut serial: String = match rng.gen_range(0..4) {
+        0 => PREFIXES.choose(rng).unwrap().to_string(),
+        1 => [
+            pick(rng, b"WZDLMCKX"),
+            pick(rng, b"0123456789A"),
+            pick(rng, b"CDEFHJKLMRSTWXYZAB"),
+            pick(rng, b"0123456789:@AZ["),
+            pick(rng, b"0123456789:@AZ["),
+            pick(rng, b"0123456789:@AZ["),
+        ]
+        .iter()
+        .collect(),
+        _ => (0..rng.gen_range(0..8))
+            .map(|_| pick(rng, b"WZDLMCK0123456789abz_ "))
+            .collect(),
+    };
+    let tail_len = rng.gen_range(0..6);
+    serial.extend((0..tail_len).map(|_| pick(rng, b"0123456789ABCDEFMZ")));
+    match rng.gen_range(0..10) {
+        0 => serial.push_str("TEST"),
+        1 => serial.insert_str(0, "ingte"),
+        2 => serial.insert(0, '\n'),
+        _ => {}
+    }
+    json!(serial)
+}
+
+fn version(rng: &mut StdRng) -> Value {
+    let part = |rng: &mut StdRng| rng.gen_range(0..1200).to_string();
+    json!(match rng.gen_range(0..5) {
+        0 => part(rng),
+        1 => format!("{}.{}", part(rng), part(rng)),
+        2 => format!("{}.{}.{}", part(rng), part(rng), part(rng)),
+        3 => format!("{}.{}.{}-debug", part(rng), part(rng), part(rng)),
+        _ => format!("{} . {}", part(rng), part(rng)),
+    })
+}
+
+fn ate_id(rng: &mut StdRng) -> Value {
+    let pick = |rng: &mut StdRng, options: &[&str]| options.choose(rng).unwrap().to_string();
+    let category = pick(rng, &["DW", "SW", "hw", "ATE1"]);
+    let kind = pick(rng, &["MM", "RF", "W"]);
+    let station = pick(rng, &["1", "2", "RF01"]);
+    let factory = pick(rng, &["Flex", "VN", "vietnam", "ZH", ""]);
+    let number = pick(rng, &["1", "12", "", "007"]);
+    let city = pick(rng, &["ZH", "HN", ""]);
+    json!(match rng.gen_range(0..5) {
+        0 => format!("{category}.{kind}.{station}-{factory}.{number}.{city}"),
+        1 => format!("{category}.{kind}.{station}.{number}-{factory}.{city}"),
+        2 => format!("{category}.2.5.{factory}{number}"),
+        3 => format!("{category}.W.2.5-{factory}.{number}.{city}"),
+        _ => format!("{category}.{station}.{factory}{number}"),
+    })
+}
+
+fn brand_name(rng: &mut StdRng) -> Value {
+    const NAMES: &[&str] = &[
+        "fossil",
+        "universal",
+        "universal_citizen",
+        "citizen",
+        "mk",
+        "ax",
+        "ea",
+        "dkny",
+        "portfolio",
+        "com.fossil.q",
+        "com.misfit.relic.staging",
+        "com.skagen.connected",
+    ];
+    let name = NAMES.choose(rng).unwrap();
+    json!(match rng.gen_range(0..4) {
+        0 => name.to_string(),
+        1 => format!("{}_prd", name),
+        2 => format!("{}_stg", name),
+        _ => format!("x{}y", name.to_uppercase()),
+    })
+}
+
+fn crc32(rng: &mut StdRng) -> Value {
+    const KNOWN: &[u32] = &[296, 4989, 10918, 27543, 41942, 64249];
+    let crc = match rng.gen_bool(0.5) {
+        true => *KNOWN.choose(rng).unwrap(),
+        false => rng.gen_range(0..65536),
+    };
+    match rng.gen_range(0..3) {
+        0 => json!(crc),
+        1 => json!(crc.to_string()),
+        _ => json!(crc as f64),
+    }
+}
+
+/// Keyword arguments to try each method with.
+fn param_sets(method: &str) -> Vec<Map<String, Value>> {
+    let mut sets = vec![Map::new()];
+    if matches!(
+        method,
+        "datetime_to_db_datetime" | "convert_bson_rfc3339_datetime"
+    ) {
+        for keep in [json!(false), json!(0), json!("")] {
+            let mut params = Map::new();
+            params.insert("keep_zero_microsecond".into(), keep);
+            sets.push(params);
+        }
+    }
+    let mut unexpected = Map::new();
+    unexpected.insert("unexpected".into(), json!(1));
+    sets.push(unexpected);
+    sets
+}
+
+/// What a call came to, in comparable form.
+fn outcome(result: Result<Value, TransformError>) -> Result<Value, String> {
+    result.map_err(|err| match err {
+        TransformError::Python {
+            type_name, message, ..
+        } => format!("{}: {}", type_name, message),
+        err => format!("{}: {}", err.category().as_str(), err),
+    })
+}
+
+/// Python's `while timestamp > MAX_SECOND_TIMESTAMP` never ends on a
+/// string `float()` reads as infinity; the ports stop instead.
+fn hangs_python(input: &Value) -> bool {
+    matches!(input, Value::String(s) if s.trim().parse::<f64>().is_ok_and(f64::is_infinite))
+}
+
+/// Why the port may differ from Python here, if its module says it does.
+fn documented(
+    method: &str,
+    input: &Value,
+    python: &Result<Value, String>,
+    native: &Result<Value, String>,
+) -> Option<&'static str> {
+    match (method, input, python, native) {
+        // `_rfc3339_datetime_to_db_datetime` is commented out of test.py;
+        // golden.rs checks the port against field_transformer.py instead.
+        ("convert_bson_rfc3339_datetime", _, Err(error), _)
+            if error.starts_with(
+                "AttributeError: 'FieldTransformer' object has no attribute \
+                 '_rfc3339_datetime_to_db_datetime'",
+            ) =>
+        {
+            Some("follows field_transformer.py")
+        }
+        (
+            "datetime_to_db_datetime" | "datetime_to_date_index" | "datetime_to_time_index",
+            Value::String(_),
+            Err(error),
+            _,
+        ) if error.starts_with("AttributeError: 'str'") => Some("takes ISO 8601 strings"),
+        // Python's `$date` formats all need a `T`.
+        (_, Value::Object(map), _, Ok(_))
+            if map
+                .get("$date")
+                .and_then(Value::as_str)
+                .is_some_and(|date| !date.contains('T')) =>
+        {
+            Some("relaxed $date strings")
+        }
+        (_, _, Ok(Value::String(id)), Ok(Value::String(lower)))
+            if method == "convert_bson_object_id" && id.to_lowercase() == *lower =>
+        {
+            Some("lowercase ObjectIds")
+        }
+        _ => None,
+    }
+}
+
+#[test]
+fn native_ports_match_python() {
+    let seed = env_or("DIFFERENTIAL_SEED", DEFAULT_SEED);
+    let cases = env_or("DIFFERENTIAL_CASES", DEFAULT_CASES);
+    let python = Engine::builder()
+        .native_transforms(false)
+        .build()
+        .expect("engine starts");
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut corpus = fixed();
+    for generate in GENERATORS {
+        corpus.extend((0..cases).map(|_| generate(&mut rng)));
+    }
+
+    let mut calls = 0;
+    let mut excused = BTreeMap::<&str, usize>::new();
+    let mut divergences = Vec::new();
+    for method in native::methods() {
+        let port = native::lookup(method).expect("listed methods have ports");
+        for params in param_sets(method) {
+            for input in &corpus {
+                if hangs_python(input) {
+                    *excused
+                        .entry("Python never returns on infinity")
+                        .or_default() += 1;
+                    continue;
+                }
+                let expected = outcome(python.transform(input, method, &params));
+                let actual = outcome(port(input, &params));
+                calls += 1;
+                if actual == expected {
+                    continue;
+                }
+                match documented(method, input, &expected, &actual) {
+                    Some(reason) => *excused.entry(reason).or_default() += 1,
+                    None => divergences.push(format!(
+                        "{}({}, **{}):\n  python: {:?}\n  native: {:?}",
+                        method,
+                        input,
+                        Value::Object(params.clone()),
+                        expected,
+                        actual
+                    )),
+                }
+            }
+        }
+    }
+
+    if env::var_os("DIFFERENTIAL_VERBOSE").is_some() {
+        for (reason, count) in &excused {
+            eprintln!("{} documented differences: {}", count, reason);
+        }
+    }
+    assert!(
+        divergences.is_empty(),
+        "{} of {} calls diverge (seed {:#x}):\n{}",
+        divergences.len(),
+        calls,
+        seed,
+        divergences.join("\n")
+    );
+}
@@ -0,0 +1,110 @@
+//! Native ports against what the Python methods returned for the same
+//! inputs, recorded in `tests/golden` by `generate.py`.
+
+use std::{fs, path::Path};
+
+use pyo3_tests::{native, TransformError};
+use serde_json::{json, Map, Value};
+
+fn check(method: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.jsonl", method));
+    let golden = fs::read_to_string(&path).unwrap_or_else(|err| panic!("{:?}: {}", path, err));
+    let transform = native::lookup(method).expect("method has a native port");
+
+    let mut cases = 0;
+    let mut mismatches = Vec::new();
+    for line in golden.lines() {
+        let case: Value = serde_json::from_str(line).expect("golden line is JSON");
+        let expected = match (case.get("output"), case.get("error")) {
+            (Some(output), _) => Ok(output.clone()),
+            (_, Some(Value::String(error))) => Err(error.clone()),
+            _ => panic!("golden line has neither output nor error: {}", line),
+        };
+        let actual = transform(&case["input"], &Map::new()).map_err(|err| match err {
+            TransformError::Python { type_name, .. } => type_name,
+            err => err.category().as_str().to_owned(),
+        });
+
+        cases += 1;
+        if actual != expected {
+            mismatches.push(format!(
+                "{}: expected {:?}, got {:?}",
+                case["input"], expected, actual
+            ));
+        }
+    }
+
+    assert!(cases > 0, "{:?} has no cases", path);
+    assert!(
+        mismatches.is_empty(),
+        "{} of {} {} cases differ:\n{}",
+        mismatches.len(),
+        cases,
+        method,
+        mismatches.join("\n")
+    );
+}
+
+#[test]
+fn extract_ate_id() {
+    check("extract_ate_id");
+}
+
+// test.py has `_rfc3339_datetime_to_db_datetime` commented out, so these
+// are worked out from field_transformer.py by hand: `iso8601.parse_date`,
+// keeping the string's local time, then `datetime_to_db_datetime`.
+#[test]
+fn convert_bson_rfc3339_datetime() {
+    let transform = native::lookup("convert_bson_rfc3339_datetime").unwrap();
+    let drop_zero = {
+        let mut params = Map::new();
+        params.insert("keep_zero_microsecond".into(), json!(false));
+        params
+    };
+    let cases = [
+        (
+            json!("2018-01-01T02:02:02.1234Z"),
+            Map::new(),
+            Ok(json!("2018-01-01 02:02:02.123400")),
+        ),
+        (
+            json!("2018-01-01T02:02:02Z"),
+            Map::new(),
+            Ok(json!("2018-01-01 02:02:02")),
+        ),
+        (
+            json!("2018-01-01T02:02:02.1234Z"),
+            drop_zero,
+            Ok(json!("2018-01-01 02:02:02")),
+        ),
+        (
+            json!({"$date": "2019-12-22T16:28:17.5+07:00"}),
+            Map::new(),
+            Ok(json!("2019-12-22 16:28:17.500000")),
+        ),
+        (json!(""), Map::new(), Ok(Value::Null)),
+        (json!({"$date": ""}), Map::new(), Ok(Value::Null)),
+        (json!({"a": 1}), Map::new(), Ok(Value::Null)),
+        (
+            json!("not a date"),
+            Map::new(),
+            Err("ParseError".to_owned()),
+        ),
+        (
+            json!({"$date": [1]}),
+            Map::new(),
+            Err("ParseError".to_owned()),
+        ),
+        (json!(5), Map::new(), Err("TypeError".to_owned())),
+    ];
+
+    for (input, params, expected) in cases {
+        let actual = transform(&input, &params).map_err(|err| match err {
+            TransformError::Python { type_name, .. } => type_name,
+            err => err.category().as_str().to_owned(),
+        });
+        assert_eq!(actual, expected, "{} with {:?}", input, params);
+    }
+}
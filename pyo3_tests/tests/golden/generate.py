@@ -0,0 +1,66 @@
+"""Regenerate the golden files from the Python FieldTransformer.
+
+Usage: python3 tests/golden/generate.py METHOD...
+
+Runs FieldTransformer.METHOD from the embedded module (test.py at the
+repository root) over every JSON value in <method>_corpus.jsonl and writes
+one {"input", "output"} or {"input", "error"} line per value to
+<method>.jsonl. Outputs go through the same rules as `convert`: tuples
+become lists and integers outside the 64-bit range are a conversion
+error. Errors are recorded by exception type only.
+"""
+
+import importlib.util
+import json
+import pathlib
+import sys
+
+GOLDEN = pathlib.Path(__file__).resolve().parent
+MODULE = GOLDEN.parents[2] / "test.py"
+
+
+def load_field_transformer():
+    spec = importlib.util.spec_from_file_location("field_transformer", MODULE)
+    module = importlib.util.module_from_spec(spec)
+    spec.loader.exec_module(module)
+    return module.FieldTransformer()
+
+
+def convert(value):
+    if isinstance(value, bool) or value is None:
+        return value
+    if isinstance(value, int):
+        if not -(2**63) <= value < 2**64:
+            raise OverflowError("integer out of 64-bit range")
+        return value
+    if isinstance(value, (list, tuple)):
+        return [convert(item) for item in value]
+    if isinstance(value, dict):
+        return {key: convert(item) for key, item in value.items()}
+    return value
+
+
+def generate(field_transformer, method):
+    corpus = GOLDEN / f"{method}_corpus.jsonl"
+    with corpus.open(encoding="utf-8") as lines, \
+            (GOLDEN / f"{method}.jsonl").open("w", encoding="utf-8") as out:
+        for line in lines:
+            value = json.loads(line)
+            try:
+                case = {"input": value,
+                        "output": convert(field_transformer.transform(value, method))}
+            except OverflowError:
+                case = {"input": value, "error": "conversion"}
+            except Exception as err:  # pylint: disable=broad-except
+                case = {"input": value, "error": type(err).__name__}
+            out.write(json.dumps(case, ensure_ascii=False) + "\n")
+
+
+def main():
+    field_transformer = load_field_transformer()
+    for method in sys.argv[1:]:
+        generate(field_transformer, method)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,110 @@
+//! `WorkerPool` over real `transform-worker` processes: ordering,
+//! backpressure and replacing a worker that stops answering.
+
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use pyo3_tests::{
+    limits::Limit, pool::WorkerPoolBuilder, SandboxPolicy, Task, TransformError, WorkerConfig,
+    WorkerPool,
+};
+use serde_json::{json, Value};
+
+/// Sleeps `value["sleep"]` seconds, then answers `value["id"]`.
+const SLEEPY: &str = "import time\ntime.sleep(value['sleep'])\noutput = value['id']";
+
+fn builder() -> WorkerPoolBuilder {
+    WorkerPool::builder()
+        .program(PathBuf::from(env!("CARGO_BIN_EXE_transform-worker")))
+        .config(WorkerConfig {
+            sandbox: SandboxPolicy::default().allow_module("time"),
+            ..WorkerConfig::default()
+        })
+}
+
+fn sleepy(id: u64, sleep: f64) -> Task {
+    Task::CustomCode {
+        value: json!({"id": id, "sleep": sleep}),
+        custom_code: SLEEPY.into(),
+    }
+}
+
+#[test]
+fn results_come_back_in_submission_order() {
+    let pool = builder().workers(4).build().unwrap();
+    // Later tasks finish first.
+    let tasks = (0..12).map(|id| sleepy(id, (12 - id) as f64 * 0.02));
+
+    let mut results = Vec::new();
+    pool.process(tasks, |outcome| results.push(outcome));
+
+    let expected: Vec<_> = (0..12).map(|id| Ok(json!(id))).collect();
+    assert_eq!(results, expected);
+}
+
+#[test]
+fn errors_keep_their_place() {
+    let pool = builder().workers(2).build().unwrap();
+    let tasks = [
+        Task::Method {
+            value: json!("4.5.2"),
+            method: "normalize_version".into(),
+            params: Default::default(),
+        },
+        Task::CustomCode {
+            value: Value::Null,
+            custom_code: "output = 1 / 0".into(),
+        },
+        sleepy(2, 0.0),
+    ];
+
+    let mut results = Vec::new();
+    pool.process(tasks, |outcome| results.push(outcome));
+
+    assert_eq!(results.len(), 3);
+    assert!(results[0].is_ok());
+    assert!(matches!(
+        &results[1],
+        Err(TransformError::Python { type_name, .. }) if type_name == "ZeroDivisionError"
+    ));
+    assert_eq!(results[2], Ok(json!(2)));
+}
+
+#[test]
+fn submissions_past_capacity_are_handed_back() {
+    let pool = builder().workers(1).queue_capacity(2).build().unwrap();
+
+    assert!(pool.try_submit(sleepy(0, 0.0)).is_ok());
+    assert!(pool.try_submit(sleepy(1, 0.0)).is_ok());
+    let rejected = pool.try_submit(sleepy(2, 0.0)).unwrap_err();
+    assert_eq!(rejected, sleepy(2, 0.0));
+
+    assert_eq!(pool.recv(), Some(Ok(json!(0))));
+    assert!(pool.try_submit(rejected).is_ok());
+    assert_eq!(pool.recv(), Some(Ok(json!(1))));
+    assert_eq!(pool.recv(), Some(Ok(json!(2))));
+    assert_eq!(pool.recv(), None);
+}
+
+#[test]
+fn hung_worker_is_killed_and_replaced() {
+    let timeout = Duration::from_millis(500);
+    let pool = builder().workers(1).task_timeout(timeout).build().unwrap();
+
+    let started = Instant::now();
+    let mut results = Vec::new();
+    pool.process([sleepy(0, 60.0), sleepy(1, 0.0)], |outcome| {
+        results.push(outcome)
+    });
+
+    assert!(started.elapsed() < Duration::from_secs(30));
+    assert_eq!(
+        results,
+        [
+            Err(TransformError::Timeout(Limit::WallClock(timeout))),
+            Ok(json!(1))
+        ]
+    );
+}
@@ -1,3 +1,61 @@
+mod runner;
+
+use std::{env, path::Path, process};
+
+use runner::{RunError, Runner};
+
+const USAGE: &str = "usage: rustpython_tests [-p DIR]... [SCRIPT.py | MODULE]
+
+Runs SCRIPT.py, or the embedded MODULE (default: class_a), on RustPython.
+Each -p DIR is added to the module search path.";
+
 fn main() {
-    println!("Hello, world!");
+    let mut runner = Runner::new();
+    let mut target = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-p" | "--path" => match args.next() {
+                Some(dir) => runner = runner.search_path(dir),
+                None => exit_usage(),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if target.is_none() => target = Some(arg),
+            _ => exit_usage(),
+        }
+    }
+
+    let target = target.unwrap_or_else(|| "class_a".to_owned());
+    let result = if target.ends_with(".py") {
+        runner.run_file(Path::new(&target))
+    } else {
+        runner.run_embedded(&target)
+    };
+
+    match result {
+        Ok(output) => {
+            print!("{}", output.stdout);
+            eprint!("{}", output.stderr);
+        }
+        Err(RunError::Python {
+            traceback, output, ..
+        }) => {
+            print!("{}", output.stdout);
+            eprint!("{}{}", output.stderr, traceback);
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
+fn exit_usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
 }
@@ -0,0 +1,372 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use rustpython_vm::{
+    builtins::{PyBaseExceptionRef, PyTuple},
+    import, AsObject, Interpreter, PyObjectRef, PyResult, Settings, VirtualMachine,
+};
+
+/// Python modules compiled into the binary, importable by name from any
+/// script the runner executes.
+pub const EMBEDDED_MODULES: &[(&str, &str)] = &[
+    ("class_a", include_str!("class_a.py")),
+    ("class_b", include_str!("class_b.py")),
+];
+
+const HOST_CODE: &str = r#"
+import importlib.abc
+import importlib.util
+import io
+import sys
+import traceback
+
+
+class EmbeddedFinder(importlib.abc.MetaPathFinder, importlib.abc.Loader):
+    """Serves modules from source strings held by the host.
+
+    Installed after the path-based finder, so a module file on the search
+    path takes precedence over an embedded module of the same name.
+    """
+
+    def __init__(self, sources):
+        self.sources = sources
+
+    def find_spec(self, name, path=None, target=None):
+        if name not in self.sources:
+            return None
+        return importlib.util.spec_from_loader(name, self, origin=f"<embedded {name}.py>")
+
+    def create_module(self, spec):
+        return None
+
+    def exec_module(self, module):
+        filename = f"<embedded {module.__name__}.py>"
+        module.__file__ = filename
+        exec(compile(self.sources[module.__name__], filename, "exec"), module.__dict__)
+
+
+def install(sources):
+    sys.meta_path.append(EmbeddedFinder(dict(sources)))
+
+
+def run(source, filename):
+    stdout, stderr = io.StringIO(), io.StringIO()
+    host_stdout, host_stderr = sys.stdout, sys.stderr
+    sys.stdout, sys.stderr = stdout, stderr
+    main = type(sys)("__main__")
+    main.__file__ = filename
+    sys.modules["__main__"] = main
+    try:
+        exec(compile(source, filename, "exec"), main.__dict__)
+        error = None
+    except BaseException as exc:
+        tb = exc.__traceback__
+        while tb is not None and tb.tb_frame.f_code.co_filename == "<source>":
+            tb = tb.tb_next
+        lines = traceback.format_exception(type(exc), exc, tb)
+        error = (type(exc).__name__, str(exc), "".join(lines))
+    finally:
+        sys.stdout, sys.stderr = host_stdout, host_stderr
+    return stdout.getvalue(), stderr.getvalue(), error
+"#;
+
+/// Text a script wrote to `sys.stdout` and `sys.stderr`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+#[derive(Debug)]
+pub enum RunError {
+    /// The script file could not be read.
+    Io(PathBuf, io::Error),
+    /// No embedded module has the requested name.
+    UnknownModule(String),
+    /// The script raised; `output` holds what it printed before that.
+    Python {
+        type_name: String,
+        message: String,
+        traceback: String,
+        output: Output,
+    },
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            RunError::UnknownModule(name) => write!(f, "no embedded module named '{}'", name),
+            RunError::Python {
+                type_name, message, ..
+            } => write!(f, "{}: {}", type_name, message),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+/// Runs Python scripts on RustPython with a configurable module search
+/// path.
+///
+/// Imports resolve against, in order: the directory of the script being
+/// run, each directory added with `search_path`, the frozen standard
+/// library, then the embedded modules. Every run gets a fresh interpreter.
+pub struct Runner {
+    search_path: Vec<PathBuf>,
+    embedded: Vec<(String, String)>,
+}
+
+impl Default for Runner {
+    fn default() -> Self {
+        Runner {
+            search_path: Vec::new(),
+            embedded: EMBEDDED_MODULES
+                .iter()
+                .map(|(name, source)| (name.to_string(), source.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl Runner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn search_path(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.search_path.push(dir.into());
+        self
+    }
+
+    /// Run a script file as `__main__`.
+    pub fn run_file(&self, path: &Path) -> Result<Output, RunError> {
+        let source = fs::read_to_string(path).map_err(|err| RunError::Io(path.to_owned(), err))?;
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+
+        self.run(&source, &path.display().to_string(), Some(dir))
+    }
+
+    /// Run an embedded module as `__main__`.
+    pub fn run_embedded(&self, name: &str) -> Result<Output, RunError> {
+        let (_, source) = self
+            .embedded
+            .iter()
+            .find(|(embedded, _)| embedded == name)
+            .ok_or_else(|| RunError::UnknownModule(name.to_owned()))?;
+
+        self.run(source, &format!("<embedded {}.py>", name), None)
+    }
+
+    fn run(&self, source: &str, filename: &str, dir: Option<&Path>) -> Result<Output, RunError> {
+        let mut settings = Settings::default();
+        settings.write_bytecode = false;
+        settings.path_list = dir
+            .into_iter()
+            .chain(self.search_path.iter().map(PathBuf::as_path))
+            .map(|dir| dir.display().to_string())
+            .collect();
+
+        let interpreter = Interpreter::with_init(settings, |vm| {
+            vm.add_native_modules(rustpython_stdlib::get_module_inits());
+            vm.add_frozen(rustpython_pylib::FROZEN_STDLIB);
+        });
+
+        interpreter.enter(|vm| {
+            let run = || -> PyResult<(Output, Option<PyObjectRef>)> {
+                let host = import::import_source(vm, "_runner", HOST_CODE)?;
+                let embedded: Vec<PyObjectRef> = self
+                    .embedded
+                    .iter()
+                    .map(|(name, source)| vm.new_tuple((name.clone(), source.clone())).into())
+                    .collect();
+                host.get_attr("install", vm)?
+                    .call((vm.ctx.new_list(embedded),), vm)?;
+
+                let result = host
+                    .get_attr("run", vm)?
+                    .call((source.to_owned(), filename.to_owned()), vm)?;
+                let [stdout, stderr, error] = tuple_items::<3>(vm, result)?;
+                let output = Output {
+                    stdout: text(vm, &stdout)?,
+                    stderr: text(vm, &stderr)?,
+                };
+                Ok((output, (!vm.is_none(&error)).then_some(error)))
+            };
+
+            match run() {
+                Ok((output, None)) => Ok(output),
+                Ok((output, Some(error))) => {
+                    let describe = || -> PyResult<RunError> {
+                        let [type_name, message, traceback] = tuple_items::<3>(vm, error)?;
+                        Ok(RunError::Python {
+                            type_name: text(vm, &type_name)?,
+                            message: text(vm, &message)?,
+                            traceback: text(vm, &traceback)?,
+                            output: output.clone(),
+                        })
+                    };
+                    Err(describe().unwrap_or_else(|exc| host_error(vm, exc)))
+                }
+                Err(exc) => Err(host_error(vm, exc)),
+            }
+        })
+    }
+}
+
+fn tuple_items<const N: usize>(
+    vm: &VirtualMachine,
+    obj: PyObjectRef,
+) -> PyResult<[PyObjectRef; N]> {
+    let tuple = obj
+        .downcast::<PyTuple>()
+        .map_err(|_| vm.new_type_error("expected a tuple".to_owned()))?;
+    <[PyObjectRef; N]>::try_from(tuple.as_slice().to_vec())
+        .map_err(|_| vm.new_type_error(format!("expected a tuple of {} items", N)))
+}
+
+fn text(vm: &VirtualMachine, obj: &PyObjectRef) -> PyResult<String> {
+    Ok(obj.str(vm)?.as_str().to_owned())
+}
+
+/// An exception raised by the runner's own setup rather than the script.
+fn host_error(vm: &VirtualMachine, exc: PyBaseExceptionRef) -> RunError {
+    let mut traceback = String::new();
+    let _ = vm.write_exception(&mut traceback, &exc);
+
+    RunError::Python {
+        type_name: exc.class().name().to_string(),
+        message: exc
+            .as_object()
+            .str(vm)
+            .map(|s| s.as_str().to_owned())
+            .unwrap_or_default(),
+        traceback,
+        output: Output::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// A scratch directory for one test, removed when dropped.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(test: &str) -> Self {
+            let dir =
+                env::temp_dir().join(format!("rustpython_tests-{}-{}", test, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            Scratch(dir)
+        }
+
+        fn file(&self, name: &str, contents: &str) -> PathBuf {
+            let path = self.0.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+            path
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn class_a_imports_the_embedded_class_b() {
+        let output = Runner::new().run_embedded("class_a").unwrap();
+        assert_eq!(output.stdout, "b\n");
+        assert_eq!(output.stderr, "");
+    }
+
+    #[test]
+    fn scripts_import_siblings_and_search_path_modules() {
+        let scratch = Scratch::new("imports");
+        let script = scratch.file(
+            "app/main.py",
+            "import sibling\nimport shared\nimport sys\nprint(sibling.NAME, shared.NAME)\nprint('oops', file=sys.stderr)\n",
+        );
+        scratch.file("app/sibling.py", "NAME = 'sibling'\n");
+        scratch.file("lib/shared.py", "NAME = 'shared'\n");
+
+        let output = Runner::new()
+            .search_path(scratch.0.join("lib"))
+            .run_file(&script)
+            .unwrap();
+        assert_eq!(
+            output,
+            Output {
+                stdout: "sibling shared\n".into(),
+                stderr: "oops\n".into(),
+            }
+        );
+
+        match Runner::new().run_file(&script) {
+            Err(RunError::Python { type_name, .. }) => {
+                assert_eq!(type_name, "ModuleNotFoundError")
+            }
+            other => panic!("expected an import error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn on_disk_modules_shadow_embedded_ones() {
+        let scratch = Scratch::new("shadow");
+        scratch.file(
+            "class_b.py",
+            "class B:\n    def print_b(self):\n        print('b from disk')\n",
+        );
+
+        let output = Runner::new()
+            .search_path(&scratch.0)
+            .run_embedded("class_a")
+            .unwrap();
+        assert_eq!(output.stdout, "b from disk\n");
+    }
+
+    #[test]
+    fn raising_scripts_keep_what_they_printed() {
+        let scratch = Scratch::new("raise");
+        let script = scratch.file(
+            "fail.py",
+            "print('before')\nraise ValueError('boom')\nprint('after')\n",
+        );
+
+        match Runner::new().run_file(&script) {
+            Err(RunError::Python {
+                type_name,
+                message,
+                traceback,
+                output,
+            }) => {
+                assert_eq!(type_name, "ValueError");
+                assert_eq!(message, "boom");
+                assert!(traceback.contains("ValueError: boom"), "{}", traceback);
+                assert_eq!(output.stdout, "before\n");
+            }
+            other => panic!("expected a Python error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_inputs_are_reported() {
+        assert!(matches!(
+            Runner::new().run_embedded("class_c"),
+            Err(RunError::UnknownModule(name)) if name == "class_c"
+        ));
+        assert!(matches!(
+            Runner::new().run_file(Path::new("/nonexistent/script.py")),
+            Err(RunError::Io(..))
+        ));
+    }
+}